@@ -0,0 +1,335 @@
+//! The C-compatible ABI between kadouchi and native libraries that provide
+//! intrinsics.
+//!
+//! A native library is a `cdylib` named after the `.ku` library it accompanies
+//! (`libfoo.so` for `foo.ku` on Linux). It declares the ABI version it was
+//! built against with `abi_version!()` and defines its intrinsics with
+//! `intrinsic!`:
+//!
+//! ```ignore
+//! #[macro_use]
+//! extern crate kadouchi_abi;
+//!
+//! use kadouchi_abi::{Arg, Dimensions};
+//!
+//! abi_version!();
+//!
+//! intrinsic!(fn pair(args: &[Arg]) -> Result<Dimensions, String> {
+//!     if args.len() != 2 {
+//!         return Err(format!("pair expects 2 arguments, got {}", args.len()));
+//!     }
+//!     Ok(Dimensions::new(2, 1))
+//! });
+//! ```
+//!
+//! The intrinsic is then available in `foo.ku` as `intrinsic("pair")`.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::slice;
+use std::str;
+
+/// The version of the ABI. Bumped on every incompatible change; kadouchi
+/// refuses to load native libraries built against a different version.
+pub const ABI_VERSION: u32 = 2;
+
+/// The name of the symbol that holds the ABI version of a native library.
+pub const ABI_VERSION_SYMBOL: &[u8] = b"KADOUCHI_ABI_VERSION";
+
+/// The size of the buffer for the error messages of intrinsics.
+pub const ERROR_CAPACITY: usize = 512;
+
+/// The signature of the functions generated by `intrinsic!`. The function
+/// initializes `this` from the `arg_count` arguments at `args` and returns
+/// `true` on success. On failure, it writes an error message to `error`.
+pub type IntrinsicFn = unsafe extern "C" fn(
+    this: *mut Dimensions,
+    args: *const RawArg,
+    arg_count: usize,
+    error: *mut ErrorBuf,
+) -> bool;
+
+/// The width and height of an object, in cells. Cell types are 1×1 and
+/// things that aren't used for matching data, like modules, are 0×0.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Dimensions {
+    pub width: i16,
+    pub height: i16,
+}
+
+impl Dimensions {
+    pub fn new(width: i16, height: i16) -> Self {
+        Dimensions { width, height }
+    }
+}
+
+/// A string slice borrowed across the ABI boundary.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct StrSlice {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl StrSlice {
+    pub fn new(string: &str) -> Self {
+        StrSlice {
+            ptr: string.as_ptr(),
+            len: string.len(),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The slice must point to valid UTF-8 that outlives `'a`.
+    pub unsafe fn as_str<'a>(&self) -> &'a str {
+        str::from_utf8_unchecked(slice::from_raw_parts(self.ptr, self.len))
+    }
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ArgTag {
+    Str = 0,
+    Object = 1,
+    Int = 2,
+    Float = 3,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union ArgValue {
+    pub string: StrSlice,
+    pub object: Dimensions,
+    pub int: i64,
+    pub float: f64,
+}
+
+/// An argument as passed across the ABI boundary: a tagged union of a string
+/// literal, an object and a number literal.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RawArg {
+    pub tag: ArgTag,
+    pub value: ArgValue,
+}
+
+impl RawArg {
+    pub fn string(string: &str) -> Self {
+        RawArg {
+            tag: ArgTag::Str,
+            value: ArgValue {
+                string: StrSlice::new(string),
+            },
+        }
+    }
+
+    pub fn object(dimensions: Dimensions) -> Self {
+        RawArg {
+            tag: ArgTag::Object,
+            value: ArgValue { object: dimensions },
+        }
+    }
+
+    pub fn int(int: i64) -> Self {
+        RawArg {
+            tag: ArgTag::Int,
+            value: ArgValue { int },
+        }
+    }
+
+    pub fn float(float: f64) -> Self {
+        RawArg {
+            tag: ArgTag::Float,
+            value: ArgValue { float },
+        }
+    }
+
+    /// # Safety
+    ///
+    /// A string argument must point to valid UTF-8 that outlives `'a`.
+    pub unsafe fn to_arg<'a>(&self) -> Arg<'a> {
+        match self.tag {
+            ArgTag::Str => Arg::Str(self.value.string.as_str()),
+            ArgTag::Object => Arg::Object(self.value.object),
+            ArgTag::Int => Arg::Int(self.value.int),
+            ArgTag::Float => Arg::Float(self.value.float),
+        }
+    }
+}
+
+/// An argument of an intrinsic.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Arg<'a> {
+    Str(&'a str),
+    Object(Dimensions),
+    Int(i64),
+    Float(f64),
+}
+
+/// A buffer owned by the caller, for the error message of an intrinsic.
+/// Messages longer than the capacity are truncated.
+#[repr(C)]
+pub struct ErrorBuf {
+    pub ptr: *mut u8,
+    pub capacity: usize,
+    pub len: usize,
+}
+
+impl ErrorBuf {
+    /// # Safety
+    ///
+    /// `buffer` must outlive the returned `ErrorBuf`.
+    pub unsafe fn new(buffer: &mut [u8]) -> Self {
+        ErrorBuf {
+            ptr: buffer.as_mut_ptr(),
+            capacity: buffer.len(),
+            len: 0,
+        }
+    }
+
+    pub fn write(&mut self, message: &str) {
+        let mut len = message.len().min(self.capacity);
+        while !message.is_char_boundary(len) {
+            len -= 1;
+        }
+        unsafe {
+            slice::from_raw_parts_mut(self.ptr, len).copy_from_slice(&message.as_bytes()[..len]);
+        }
+        self.len = len;
+    }
+
+    pub fn message(&self) -> &str {
+        unsafe { StrSlice { ptr: self.ptr, len: self.len }.as_str() }
+    }
+}
+
+/// Calls the body of an intrinsic with the raw arguments. Used by the code
+/// generated by `intrinsic!`.
+///
+/// # Safety
+///
+/// The pointers must be valid as described by `IntrinsicFn`.
+pub unsafe fn call<F>(
+    body: F,
+    this: *mut Dimensions,
+    args: *const RawArg,
+    arg_count: usize,
+    error: *mut ErrorBuf,
+) -> bool
+where
+    F: FnOnce(&[Arg]) -> Result<Dimensions, String>,
+{
+    let args = if arg_count == 0 {
+        Vec::new()
+    } else {
+        slice::from_raw_parts(args, arg_count)
+            .iter()
+            .map(|arg| arg.to_arg())
+            .collect()
+    };
+
+    match panic::catch_unwind(AssertUnwindSafe(|| body(&args))) {
+        Ok(Ok(dimensions)) => {
+            *this = dimensions;
+            true
+        }
+        Ok(Err(message)) => {
+            (*error).write(&message);
+            false
+        }
+        Err(_) => {
+            (*error).write("The intrinsic panicked");
+            false
+        }
+    }
+}
+
+/// Exports the ABI version symbol. Every native library must use this once.
+#[macro_export]
+macro_rules! abi_version {
+    () => {
+        #[no_mangle]
+        pub static KADOUCHI_ABI_VERSION: u32 = $crate::ABI_VERSION;
+    };
+}
+
+/// Defines an intrinsic and exports it with the ABI of `IntrinsicFn`, named
+/// after the function.
+#[macro_export]
+macro_rules! intrinsic {
+    (fn $name:ident($args:ident: &[Arg]) -> Result<Dimensions, String> $body:block) => {
+        #[no_mangle]
+        pub unsafe extern "C" fn $name(
+            this: *mut $crate::Dimensions,
+            args: *const $crate::RawArg,
+            arg_count: usize,
+            error: *mut $crate::ErrorBuf,
+        ) -> bool {
+            fn body($args: &[$crate::Arg]) -> Result<$crate::Dimensions, String> {
+                $body
+            }
+            $crate::call(body, this, args, arg_count, error)
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    abi_version!();
+
+    intrinsic!(fn width(args: &[Arg]) -> Result<Dimensions, String> {
+        let mut width = 0;
+        for arg in args {
+            match arg {
+                Arg::Str(_) => width += 1,
+                Arg::Object(dimensions) => width += dimensions.width,
+                Arg::Int(int) => width += *int as i16,
+                Arg::Float(_) => return Err("width expects no floats".to_owned()),
+            }
+        }
+        if width == 0 {
+            return Err("width expects arguments".to_owned());
+        }
+        Ok(Dimensions::new(width, 1))
+    });
+
+    fn call_width(args: &[RawArg]) -> Result<Dimensions, String> {
+        let func: IntrinsicFn = width;
+        let mut this = Dimensions::default();
+        let mut buffer = [0; ERROR_CAPACITY];
+        unsafe {
+            let mut error = ErrorBuf::new(&mut buffer);
+            if func(&mut this, args.as_ptr(), args.len(), &mut error) {
+                Ok(this)
+            } else {
+                Err(error.message().to_owned())
+            }
+        }
+    }
+
+    #[test]
+    fn test_intrinsic() {
+        let args = [RawArg::string("cell"), RawArg::object(Dimensions::new(2, 1))];
+
+        assert_eq!(call_width(&args), Ok(Dimensions::new(3, 1)));
+        assert_eq!(call_width(&[RawArg::int(4)]), Ok(Dimensions::new(4, 1)));
+        assert_eq!(
+            call_width(&[RawArg::float(0.5)]),
+            Err("width expects no floats".to_owned())
+        );
+        assert_eq!(call_width(&[]), Err("width expects arguments".to_owned()));
+        assert_eq!(KADOUCHI_ABI_VERSION, ABI_VERSION);
+    }
+
+    #[test]
+    fn test_error_truncation() {
+        let mut buffer = [0; 4];
+        let mut error = unsafe { ErrorBuf::new(&mut buffer) };
+        error.write("abcä");
+
+        assert_eq!(error.message(), "abc");
+    }
+}
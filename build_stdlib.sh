@@ -1,4 +0,0 @@
-#!/bin/sh
-
-cd src/stdlib
-rustc --crate-type cdylib std.rs
\ No newline at end of file
@@ -0,0 +1,582 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use failure::Error;
+
+use data::CellType;
+use datetime::FormatKind;
+use errors::NoSourceFileError;
+use validate::{file_layout, header, is_repeated, main_layout, Value};
+use {Schema, LIBNAME_PRELUDE, LIBNAME_STD};
+
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in",
+    "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+fn words(name: &str) -> Vec<String> {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+fn escape_keyword(mut name: String) -> String {
+    if KEYWORDS.contains(&name.as_str()) {
+        name.push('_');
+    }
+    name
+}
+
+/// A field name like `day_of_week`.
+fn snake_case(name: &str) -> String {
+    let name = words(name)
+        .iter()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("_");
+    match name.chars().next() {
+        None => "field".to_owned(),
+        Some(c) if c.is_numeric() => format!("_{}", name),
+        Some(_) => escape_keyword(name),
+    }
+}
+
+/// A type or variant name like `DayOfWeek`.
+fn camel_case(name: &str) -> String {
+    let name = words(name)
+        .iter()
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<String>();
+    match name.chars().next() {
+        None => "Item".to_owned(),
+        Some(c) if c.is_numeric() => format!("V{}", name),
+        Some(_) => escape_keyword(name),
+    }
+}
+
+/// Makes a name unique by numbering it.
+fn unique(name: String, used: &mut HashSet<String>) -> String {
+    let mut unique = name.clone();
+    let mut number = 1;
+    while used.contains(&unique) {
+        number += 1;
+        unique = format!("{}{}", name, number);
+    }
+    used.insert(unique.clone());
+    unique
+}
+
+/// How `::kadouchi::Data` is converted into a Rust type.
+#[derive(Clone, Debug, PartialEq)]
+enum Conversion {
+    /// The data is kept as is
+    Identity,
+    /// A conversion method of `Data`, like `into_string`
+    Method(&'static str),
+    /// The `from_data` function of a generated type
+    FromData(String),
+    Vec(Box<Conversion>),
+    Option(Box<Conversion>),
+}
+
+impl Conversion {
+    /// An expression that converts the data expression `data`, returning early
+    /// on errors.
+    fn apply(&self, data: &str) -> String {
+        match self {
+            Conversion::Identity => data.to_owned(),
+            Conversion::Method(method) => format!("{}.{}()?", data, method),
+            Conversion::FromData(name) => format!("{}::from_data({})?", name, data),
+            Conversion::Vec(inner) => format!("{}.into_vec({})?", data, inner.function()),
+            Conversion::Option(inner) => format!("{}.into_option({})?", data, inner.function()),
+        }
+    }
+
+    /// A function from `Data` to the result of the conversion.
+    fn function(&self) -> String {
+        match self {
+            Conversion::Identity => "Ok".to_owned(),
+            Conversion::Method(method) => format!("::kadouchi::Data::{}", method),
+            Conversion::FromData(name) => format!("{}::from_data", name),
+            Conversion::Vec(inner) => format!(
+                "|data: ::kadouchi::Data| data.into_vec({})",
+                inner.function()
+            ),
+            Conversion::Option(inner) => format!(
+                "|data: ::kadouchi::Data| data.into_option({})",
+                inner.function()
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct RustType {
+    name: String,
+    conversion: Conversion,
+}
+
+impl RustType {
+    fn new(name: &str, conversion: Conversion) -> Self {
+        RustType {
+            name: name.to_owned(),
+            conversion,
+        }
+    }
+}
+
+/// The string literals of a header row: an unnamed row of literals only.
+fn literal_alternatives<'v>(or: &'v Value, literals: &mut Vec<&'v str>) -> bool {
+    match or {
+        Value::Str(literal) => {
+            let literal = &**literal;
+            if !literals.contains(&literal) {
+                literals.push(literal);
+            }
+            true
+        }
+        Value::Or(alternatives) => alternatives
+            .iter()
+            .all(|alternative| literal_alternatives(alternative, literals)),
+        _ => false,
+    }
+}
+
+struct Generator {
+    /// The generated type definitions, in the order they were generated
+    items: Vec<String>,
+    type_names: HashSet<String>,
+}
+
+impl Generator {
+    fn type_name(&mut self, name: &str) -> String {
+        unique(camel_case(name), &mut self.type_names)
+    }
+
+    /// The Rust type of a cell type, or `None` for string literals, whose cells
+    /// carry no information.
+    fn cell(&mut self, cell: &Value, name: &str) -> Option<RustType> {
+        let rust_type = match cell {
+            Value::Str(_) => return None,
+            Value::Regexp(..) => RustType::new("String", Conversion::Method("into_string")),
+            Value::Number(cell_type) => match cell_type {
+                CellType::Int { .. } => RustType::new("i64", Conversion::Method("into_int")),
+                CellType::UInt { .. } => RustType::new("u64", Conversion::Method("into_uint")),
+                CellType::Float { .. } => RustType::new("f64", Conversion::Method("into_float")),
+                CellType::Decimal { .. } => {
+                    RustType::new("::kadouchi::Decimal", Conversion::Method("into_decimal"))
+                }
+            },
+            Value::Format(_, format) => match format.kind() {
+                FormatKind::Date => {
+                    RustType::new("::kadouchi::Date", Conversion::Method("into_date"))
+                }
+                FormatKind::Time => {
+                    RustType::new("::kadouchi::Time", Conversion::Method("into_time"))
+                }
+                FormatKind::DateTime => {
+                    RustType::new("::kadouchi::DateTime", Conversion::Method("into_datetime"))
+                }
+            },
+            Value::Or(alternatives) => {
+                let mut literals = Vec::new();
+                if literal_alternatives(cell, &mut literals) {
+                    return Some(self.tag_enum(name, &literals));
+                }
+                // The alternatives are only merged if they're all of the
+                // same type
+                let types = alternatives
+                    .iter()
+                    .map(|alternative| self.cell(alternative, name))
+                    .collect::<Vec<_>>();
+                match types.first() {
+                    Some(Some(first)) if types.iter().all(|t| t.as_ref() == Some(first)) => {
+                        first.clone()
+                    }
+                    _ => RustType::new("::kadouchi::Data", Conversion::Identity),
+                }
+            }
+            Value::And(constituents) => {
+                // A typed conversion wins over the plain string of a regexp,
+                // like in `Value::convert`
+                let types = constituents
+                    .iter()
+                    .filter_map(|constituent| self.cell(constituent, name))
+                    .collect::<Vec<_>>();
+                let typed = types.iter().position(|t| t.name != "String");
+                return types.into_iter().nth(typed.unwrap_or(0));
+            }
+            _ => RustType::new("::kadouchi::Data", Conversion::Identity),
+        };
+        Some(rust_type)
+    }
+
+    fn tag_enum(&mut self, name: &str, literals: &[&str]) -> RustType {
+        let type_name = self.type_name(name);
+        let mut variant_names = HashSet::new();
+        let variants = literals
+            .iter()
+            .map(|literal| (unique(camel_case(literal), &mut variant_names), *literal))
+            .collect::<Vec<_>>();
+
+        let mut item = String::new();
+        item.push_str("#[derive(Clone, Copy, Debug, Eq, PartialEq)]\n");
+        item.push_str(&format!("pub enum {} {{\n", type_name));
+        for (variant, _) in &variants {
+            item.push_str(&format!("    {},\n", variant));
+        }
+        item.push_str("}\n\n");
+
+        item.push_str(&format!("impl {} {{\n", type_name));
+        item.push_str(
+            "    pub fn from_data(data: ::kadouchi::Data) -> Result<Self, ::kadouchi::Error> {\n",
+        );
+        item.push_str("        match data.into_tag()?.as_str() {\n");
+        for (variant, literal) in &variants {
+            item.push_str(&format!(
+                "            {:?} => Ok({}::{}),\n",
+                literal, type_name, variant
+            ));
+        }
+        item.push_str("            tag => Err(::kadouchi::DataShapeError(\n");
+        item.push_str(&format!(
+            "                {:?}.to_owned(),\n",
+            format!("a tag of {}", type_name)
+        ));
+        item.push_str("                format!(\"{:?}\", tag),\n");
+        item.push_str("            ).into()),\n");
+        item.push_str("        }\n    }\n\n");
+
+        item.push_str("    pub fn as_str(&self) -> &'static str {\n        match self {\n");
+        for (variant, literal) in &variants {
+            item.push_str(&format!(
+                "            {}::{} => {:?},\n",
+                type_name, variant, literal
+            ));
+        }
+        item.push_str("        }\n    }\n}\n");
+
+        self.items.push(item);
+        RustType::new(&type_name, Conversion::FromData(type_name.clone()))
+    }
+
+    /// The Rust type of a layout, or `None` for layouts of string literals
+    /// only. The cells of rows without names are named after `columns`, the
+    /// cells of the preceding header row.
+    fn layout<'v>(
+        &mut self,
+        layout: &'v Value,
+        name: &str,
+        columns: Option<&[&'v str]>,
+    ) -> Option<RustType> {
+        match layout {
+            Value::Row(cells, names) => {
+                if header(layout, None).is_some() {
+                    return None;
+                }
+                let fields = cells
+                    .iter()
+                    .zip(names)
+                    .enumerate()
+                    .map(|(idx, (cell, cell_name))| {
+                        let field_name = cell_name
+                            .or_else(|| columns.and_then(|columns| columns.get(idx).cloned()))
+                            .map(str::to_owned)
+                            .unwrap_or_else(|| format!("field_{}", idx + 1));
+                        let rust_type = self.cell(cell, &field_name);
+                        (field_name, rust_type)
+                    })
+                    .collect();
+                Some(self.record_struct(name, fields))
+            }
+            Value::Col(children, _) if is_repeated(children) => {
+                // A repeated body
+                let item_name = format!("{} row", name);
+                let item = self.layout(&children[0], &item_name, columns)?;
+                Some(RustType {
+                    name: format!("Vec<{}>", item.name),
+                    conversion: Conversion::Vec(Box::new(item.conversion)),
+                })
+            }
+            Value::Col(children, names) => {
+                // A header row names only the layout right after it
+                let mut header_columns = None;
+                let mut fields = Vec::new();
+                for (idx, (child, child_name)) in children.iter().zip(names).enumerate() {
+                    if let Some(header) = header(child, *child_name) {
+                        header_columns = Some(header);
+                        fields.push((String::new(), None));
+                        continue;
+                    }
+                    let field_name = child_name
+                        .map(str::to_owned)
+                        .unwrap_or_else(|| format!("{} {}", name, idx + 1));
+                    let child_columns = header_columns.take();
+                    let child_columns = child_columns.as_ref().map(Vec::as_slice).or(columns);
+                    let rust_type = self.layout(child, &field_name, child_columns);
+                    fields.push((field_name, rust_type));
+                }
+                Some(self.record_struct(name, fields))
+            }
+            Value::Repeat(repetition, inner) => {
+                let item_name = format!("{} item", name);
+                let item = self.layout(inner, &item_name, columns)?;
+                if repetition.max == Some(1) {
+                    Some(RustType {
+                        name: format!("Option<{}>", item.name),
+                        conversion: Conversion::Option(Box::new(item.conversion)),
+                    })
+                } else {
+                    Some(RustType {
+                        name: format!("Vec<{}>", item.name),
+                        conversion: Conversion::Vec(Box::new(item.conversion)),
+                    })
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Generates a struct with the fields that have a type. The fields without
+    /// a type are still taken from the data, to keep the rest in place.
+    fn record_struct(&mut self, name: &str, fields: Vec<(String, Option<RustType>)>) -> RustType {
+        let type_name = self.type_name(name);
+        let mut field_names = HashSet::new();
+        let fields = fields
+            .into_iter()
+            .map(|(field_name, rust_type)| {
+                rust_type.map(|rust_type| (unique(snake_case(&field_name), &mut field_names), rust_type))
+            })
+            .collect::<Vec<_>>();
+
+        let mut item = String::new();
+        item.push_str("#[derive(Clone, Debug, PartialEq)]\n");
+        item.push_str(&format!("pub struct {} {{\n", type_name));
+        for (field_name, rust_type) in fields.iter().flat_map(|field| field) {
+            item.push_str(&format!("    pub {}: {},\n", field_name, rust_type.name));
+        }
+        item.push_str("}\n\n");
+
+        item.push_str(&format!("impl {} {{\n", type_name));
+        item.push_str(
+            "    pub fn from_data(data: ::kadouchi::Data) -> Result<Self, ::kadouchi::Error> {\n",
+        );
+        if fields.iter().all(Option::is_none) {
+            item.push_str("        let _ = data;\n");
+        } else {
+            item.push_str("        let mut fields = data.into_fields()?;\n");
+        }
+        for field in &fields {
+            match field {
+                Some((field_name, rust_type)) => item.push_str(&format!(
+                    "        let {} = {};\n",
+                    field_name,
+                    rust_type.conversion.apply("fields.take()?")
+                )),
+                None => item.push_str("        fields.take()?;\n"),
+            }
+        }
+        let field_names = fields
+            .iter()
+            .flat_map(|field| field)
+            .map(|(field_name, _)| field_name.as_str())
+            .collect::<Vec<_>>();
+        item.push_str(&format!(
+            "        Ok({} {{ {} }})\n",
+            type_name,
+            field_names.join(", ")
+        ));
+        item.push_str("    }\n}\n");
+
+        self.items.push(item);
+        RustType::new(&type_name, Conversion::FromData(type_name.clone()))
+    }
+}
+
+/// Generates the Rust types of a layout named `name`, and a `parse` function
+/// that parses data files into them with the schema embedded by `sources`,
+/// read from `schema`.
+fn generate(name: &str, layout: &Value, schema: &str, sources: &str) -> String {
+    let mut generator = Generator {
+        items: Vec::new(),
+        type_names: HashSet::new(),
+    };
+    let top = match generator.layout(layout, name, None) {
+        Some(ref rust_type) if rust_type.conversion == Conversion::FromData(rust_type.name.clone()) => {
+            rust_type.name.clone()
+        }
+        // Lists and layouts without data are wrapped in a struct
+        rust_type => {
+            let field = rust_type.map(|rust_type| (name.to_owned(), Some(rust_type)));
+            let fields = field.into_iter().collect();
+            generator.record_struct(&format!("{} file", name), fields).name
+        }
+    };
+
+    let mut out = String::new();
+    out.push_str(&format!(
+        "// Generated by kadouchi from {}. Do not edit.\n\n",
+        schema
+    ));
+    out.push_str(sources);
+    for item in &generator.items {
+        out.push('\n');
+        out.push_str(item);
+    }
+    out.push('\n');
+    out.push_str(&format!(
+        "/// Parses a data file into `{}`, checking it against the schema.\n",
+        top
+    ));
+    out.push_str(&format!(
+        "pub fn parse<R: ::std::io::BufRead>(reader: R) -> Result<{}, ::kadouchi::Error> {{\n",
+        top
+    ));
+    out.push_str("    let search_path = ::kadouchi::SearchPath::from_env();\n");
+    out.push_str(
+        "    let schema = ::kadouchi::parse_sources(STD, PRELUDE, LIBRARIES, &search_path)?;\n",
+    );
+    out.push_str("    let (dialect, layout) = ::kadouchi::file_layout(&schema)?;\n");
+    out.push_str("    let data = ::kadouchi::parse_reader(&dialect, &layout, reader)?;\n");
+    out.push_str(&format!("    {}::from_data(data)\n", top));
+    out.push_str("}\n");
+    out
+}
+
+/// An expression that embeds the source file at `path`.
+fn include_source(path: &Path) -> Result<String, Error> {
+    Ok(format!("include_str!({:?})", path.canonicalize()?.to_string_lossy()))
+}
+
+/// The constants that embed the sources of a schema: the standard library and
+/// the prelude, unless they're the bundled ones, and the user libraries.
+fn embed_sources(files: &[(String, Option<PathBuf>)]) -> Result<String, Error> {
+    let mut out = String::new();
+    let mut libraries = Vec::new();
+    for (name, file) in files {
+        let source = match file {
+            Some(file) => Some(include_source(file)?),
+            None => None,
+        };
+        match (name.as_str(), source) {
+            (LIBNAME_STD, source) | (LIBNAME_PRELUDE, source) => {
+                out.push_str(&format!(
+                    "/// The `{}` library of the schema, or `None` for the bundled one.\n",
+                    name
+                ));
+                let source = source.map_or("None".to_owned(), |source| format!("Some({})", source));
+                out.push_str(&format!(
+                    "pub const {}: Option<&str> = {};\n",
+                    name.to_uppercase(),
+                    source
+                ));
+            }
+            (_, Some(source)) => libraries.push(format!("    ({:?}, {}),\n", name, source)),
+            (_, None) => return Err(NoSourceFileError(name.to_owned()).into()),
+        }
+    }
+    out.push_str("/// The schema and the libraries it imports, in dependency order.\n");
+    out.push_str("pub const LIBRARIES: &[(&str, &str)] = &[\n");
+    for library in libraries {
+        out.push_str(&library);
+    }
+    out.push_str("];\n");
+    Ok(out)
+}
+
+/// Generates Rust types for the main layout of the user library, with a
+/// `parse` function that parses data files into them. The generated code
+/// depends on the `kadouchi` crate, and embeds the sources of the schema with
+/// `include_str!`, so the files have to be there when it's compiled, as in a
+/// `build.rs`. The native companions are looked up from `KADOUCHI_PATH`
+/// when it parses.
+pub fn generate_rust(schema: &Schema) -> Result<String, Error> {
+    let name = main_layout(&schema.root)?.local_name.unwrap_or("data");
+    let (_, layout) = file_layout(schema)?;
+    let sources = embed_sources(schema.files())?;
+    let path = match schema.files().last() {
+        Some((_, Some(path))) => path.canonicalize()?,
+        Some((name, None)) => return Err(NoSourceFileError(name.to_owned()).into()),
+        None => unreachable!("Invariant: a schema has the standard library."),
+    };
+
+    Ok(generate(name, &layout, &path.to_string_lossy(), &sources))
+}
+
+#[test]
+fn test_names() {
+    assert_eq!(snake_case("Day of week"), "day_of_week");
+    assert_eq!(snake_case("2nd"), "_2nd");
+    assert_eq!(snake_case("type"), "type_");
+    assert_eq!(snake_case("--"), "field");
+    assert_eq!(camel_case("data row"), "DataRow");
+    assert_eq!(camel_case("n/a"), "NA");
+    assert_eq!(camel_case("self"), "Self_");
+
+    let mut used = HashSet::new();
+    assert_eq!(unique("Day".to_owned(), &mut used), "Day");
+    assert_eq!(unique("Day".to_owned(), &mut used), "Day2");
+}
+
+#[test]
+fn test_generate() {
+    use dialect::Dialect;
+    use validate::test_schema;
+
+    let source = &br#"
+intrinsic("row") as row
+intrinsic("col") as col
+intrinsic("regexp") as regexp
+intrinsic("uint") as uint
+intrinsic("or") as or
+intrinsic("optional") as optional
+intrinsic("export") as export
+
+or("mon" "tue") as day
+
+col(
+    row("name" "day" "count")
+    col(row(regexp(".*") day uint)) as data
+    optional(row("total" "" uint(0 1000))) as total
+) as test
+
+export(test)
+"#[..];
+
+    let (_, layout) = file_layout(&test_schema(source)).unwrap();
+    let code = generate("test", &layout, "test.ku", "");
+
+    assert!(code.contains("pub enum Day {\n    Mon,\n    Tue,\n}"));
+    assert!(code.contains(
+        "pub struct DataRow {\n    pub name: String,\n    pub day: Day,\n    pub count: u64,\n}"
+    ));
+    assert!(code.contains("pub struct TotalItem {\n    pub field_3: u64,\n}"));
+    assert!(code.contains(
+        "pub struct Test {\n    pub data: Vec<DataRow>,\n    pub total: Option<TotalItem>,\n}"
+    ));
+    assert!(code.contains("let data = fields.take()?.into_vec(DataRow::from_data)?;"));
+    assert!(code.contains("Result<Test, ::kadouchi::Error>"));
+
+    // The generated conversions accept the parsed data
+    let data = ::validate::parse_reader(
+        &Dialect::default(),
+        &layout,
+        "name\tday\tcount\nalice\tmon\t5\n".as_bytes(),
+    ).unwrap();
+    let mut fields = data.into_fields().unwrap();
+    fields.take().unwrap();
+    let rows = fields.take().unwrap().into_vec(Ok).unwrap();
+    assert_eq!(rows.len(), 1);
+    let total = fields.take().unwrap().into_option(Ok).unwrap();
+    assert!(total.is_none());
+}
@@ -0,0 +1,595 @@
+use std::fmt;
+use std::vec;
+
+use failure::Error;
+
+use errors::{
+    DataShapeError, InvalidConstraintError, UnknownCellTypeError, WrongNumberOfArguments,
+};
+use span::Snippet;
+
+/// How the cells of a cell type are converted into data, and the constraints
+/// of the numeric cell types like `int(0 100)`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CellType {
+    /// An integer between `min` and `max`, inclusive
+    Int { min: i64, max: i64 },
+    UInt { min: u64, max: u64 },
+    /// A float with at most `precision` significant digits, if given
+    Float { precision: Option<u32> },
+    /// A decimal number with at most `scale` digits after the decimal point,
+    /// if given
+    Decimal { scale: Option<u32> },
+}
+
+impl CellType {
+    pub fn parse(name: &str) -> Result<CellType, Error> {
+        match name {
+            "int" => Ok(CellType::Int {
+                min: i64::min_value(),
+                max: i64::max_value(),
+            }),
+            "uint" => Ok(CellType::UInt {
+                min: 0,
+                max: u64::max_value(),
+            }),
+            "float" => Ok(CellType::Float { precision: None }),
+            "decimal" => Ok(CellType::Decimal { scale: None }),
+            _ => Err(UnknownCellTypeError(name.to_owned(), Snippet::default()).into()),
+        }
+    }
+
+    /// The numeric cell type `name` constrained by the arguments of its
+    /// intrinsic: `int(min max)`, `uint(min max)`, `float(precision)` or
+    /// `decimal(scale)`.
+    pub fn constrained(name: &str, args: &[i64]) -> Result<CellType, Error> {
+        let expected = match name {
+            "int" | "uint" => 2,
+            _ => 1,
+        };
+        if args.len() != expected {
+            return Err(WrongNumberOfArguments(expected, args.len(), Snippet::default()).into());
+        }
+        let invalid = |reason: &str| {
+            let call = format!(
+                "{}({})",
+                name,
+                args.iter().map(i64::to_string).collect::<Vec<_>>().join(" ")
+            );
+            InvalidConstraintError(call, reason.to_owned(), Snippet::default())
+        };
+
+        let cell_type = match name {
+            "int" => CellType::Int {
+                min: args[0],
+                max: args[1],
+            },
+            "uint" if args[0] < 0 => return Err(invalid("the minimum is negative").into()),
+            "uint" => CellType::UInt {
+                min: args[0] as u64,
+                max: args[1] as u64,
+            },
+            "float" | "decimal" if args[0] < 0 || args[0] > i64::from(u32::max_value()) => {
+                return Err(invalid("the number of digits is out of range").into())
+            }
+            "float" if args[0] == 0 => {
+                return Err(invalid("a float has at least one significant digit").into())
+            }
+            "float" => CellType::Float {
+                precision: Some(args[0] as u32),
+            },
+            "decimal" => CellType::Decimal {
+                scale: Some(args[0] as u32),
+            },
+            _ => return Err(UnknownCellTypeError(name.to_owned(), Snippet::default()).into()),
+        };
+        if args.len() == 2 && args[0] > args[1] {
+            return Err(invalid("the minimum is greater than the maximum").into());
+        }
+        Ok(cell_type)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CellType::Int { .. } => "int",
+            CellType::UInt { .. } => "uint",
+            CellType::Float { .. } => "float",
+            CellType::Decimal { .. } => "decimal",
+        }
+    }
+
+    /// Converts a cell, or returns `None` if it can't be represented or
+    /// violates the constraints.
+    pub fn convert(&self, cell: &str) -> Option<Data> {
+        let data = self.convert_unconstrained(cell)?;
+        match self.violation(cell, &data) {
+            Some(_) => None,
+            None => Some(data),
+        }
+    }
+
+    /// Explains why a cell that is written like a number of this type doesn't
+    /// satisfy the constraints, like `12 is out of the range 0..10`.
+    pub fn explain(&self, cell: &str) -> Option<String> {
+        let data = self.convert_unconstrained(cell)?;
+        self.violation(cell, &data)
+    }
+
+    fn convert_unconstrained(&self, cell: &str) -> Option<Data> {
+        match self {
+            CellType::Int { .. } if is_digits(unsigned(cell)?) => {
+                cell.trim_left_matches('+').parse().ok().map(Data::Int)
+            }
+            CellType::Int { .. } => None,
+            CellType::UInt { .. } if is_digits(cell) => cell.parse().ok().map(Data::UInt),
+            CellType::UInt { .. } => None,
+            CellType::Float { .. } => {
+                Number::parse(cell)?;
+                cell.parse().ok().map(Data::Float)
+            }
+            CellType::Decimal { .. } => Decimal::parse(cell).map(Data::Decimal),
+        }
+    }
+
+    fn violation(&self, cell: &str, data: &Data) -> Option<String> {
+        match (*self, data) {
+            (CellType::Int { min, max }, Data::Int(int)) if *int < min || *int > max => {
+                Some(format!("{} is out of the range {}..{}", int, min, max))
+            }
+            (CellType::UInt { min, max }, Data::UInt(uint)) if *uint < min || *uint > max => {
+                Some(format!("{} is out of the range {}..{}", uint, min, max))
+            }
+            (CellType::Float { precision: Some(precision) }, _) => {
+                let digits = Number::parse(cell)?.significant_digits();
+                if digits > precision as usize {
+                    Some(format!("{} has more than {} significant digits", cell, precision))
+                } else {
+                    None
+                }
+            }
+            (CellType::Decimal { scale: Some(scale) }, Data::Decimal(decimal))
+                if decimal.scale > scale =>
+            {
+                Some(format!(
+                    "{} has more than {} digits after the decimal point",
+                    cell, scale
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CellType {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CellType::Int { min, max } if *min == i64::min_value() && *max == i64::max_value() => {
+                formatter.write_str("int")
+            }
+            CellType::UInt { min: 0, max } if *max == u64::max_value() => {
+                formatter.write_str("uint")
+            }
+            CellType::Int { min, max } => write!(formatter, "int({} {})", min, max),
+            CellType::UInt { min, max } => write!(formatter, "uint({} {})", min, max),
+            CellType::Float { precision: Some(precision) } => {
+                write!(formatter, "float({})", precision)
+            }
+            CellType::Decimal { scale: Some(scale) } => write!(formatter, "decimal({})", scale),
+            cell_type => formatter.write_str(cell_type.name()),
+        }
+    }
+}
+
+fn is_digits(digits: &str) -> bool {
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// A number without its sign, if it has at most one.
+fn unsigned(number: &str) -> Option<&str> {
+    let unsigned = number.trim_left_matches(|c| c == '+' || c == '-');
+    if number.len() - unsigned.len() > 1 {
+        None
+    } else {
+        Some(unsigned)
+    }
+}
+
+/// A number written like `-1.5e3`, `.5` or `12`.
+struct Number<'c> {
+    negative: bool,
+    integer: &'c str,
+    fraction: &'c str,
+    exponent: Option<&'c str>,
+}
+
+impl<'c> Number<'c> {
+    fn parse(cell: &'c str) -> Option<Number<'c>> {
+        let magnitude = unsigned(cell)?;
+        let (mantissa, exponent) = match magnitude.find(|c| c == 'e' || c == 'E') {
+            Some(idx) => (&magnitude[..idx], Some(&magnitude[idx + 1..])),
+            None => (magnitude, None),
+        };
+        let (integer, fraction) = match mantissa.find('.') {
+            Some(idx) => (&mantissa[..idx], &mantissa[idx + 1..]),
+            None => (mantissa, ""),
+        };
+        let valid_exponent =
+            exponent.map_or(true, |exponent| unsigned(exponent).map_or(false, is_digits));
+        let valid_digits = (integer.is_empty() || is_digits(integer))
+            && (fraction.is_empty() || is_digits(fraction))
+            && !(integer.is_empty() && fraction.is_empty());
+        if !valid_exponent || !valid_digits {
+            return None;
+        }
+        Some(Number {
+            negative: cell.starts_with('-'),
+            integer,
+            fraction,
+            exponent,
+        })
+    }
+
+    /// The digits as written, without the leading zeros.
+    fn significant_digits(&self) -> usize {
+        let digits = self.integer.len() + self.fraction.len();
+        let leading_zeros = self
+            .integer
+            .bytes()
+            .chain(self.fraction.bytes())
+            .take_while(|b| *b == b'0')
+            .count();
+        (digits - leading_zeros).max(1)
+    }
+}
+
+/// A decimal number: `mantissa` divided by ten to the power of `scale`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Decimal {
+    pub mantissa: i64,
+    pub scale: u32,
+}
+
+impl Decimal {
+    fn parse(cell: &str) -> Option<Decimal> {
+        let number = Number::parse(cell)?;
+        if number.exponent.is_some() {
+            return None;
+        }
+        let digits = format!(
+            "{}{}{}",
+            if number.negative { "-" } else { "" },
+            number.integer,
+            number.fraction
+        );
+        Some(Decimal {
+            mantissa: digits.parse().ok()?,
+            scale: number.fraction.len() as u32,
+        })
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.mantissa.to_string();
+        let (sign, digits) = if digits.starts_with('-') {
+            ("-", &digits[1..])
+        } else {
+            ("", &digits[..])
+        };
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(formatter, "{}{}", sign, digits);
+        }
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let point = digits.len() - scale;
+        write!(formatter, "{}{}.{}", sign, &digits[..point], &digits[point..])
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Date {
+    pub year: i32,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl Date {
+    /// A date of the proleptic Gregorian calendar, or the reason why there's
+    /// no such date.
+    pub fn new(year: i32, month: u8, day: u8) -> Result<Date, String> {
+        if month < 1 || month > 12 {
+            return Err(format!("{} is not a month", month));
+        }
+        let days = days_in_month(year, month);
+        if day < 1 || day > days {
+            return Err(format!("month {} of {} has {} days", month, year, days));
+        }
+        Ok(Date { year, month, day })
+    }
+}
+
+fn is_leap_year(year: i32) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// A time of day. The second 60 is a leap second.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Time {
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+}
+
+impl Time {
+    /// A time of day, or the reason why there's no such time.
+    pub fn new(hour: u8, minute: u8, second: u8, nanosecond: u32) -> Result<Time, String> {
+        if hour > 23 {
+            return Err(format!("{} is not an hour", hour));
+        }
+        if minute > 59 {
+            return Err(format!("{} is not a minute", minute));
+        }
+        if second > 60 {
+            return Err(format!("{} is not a second", second));
+        }
+        Ok(Time {
+            hour,
+            minute,
+            second,
+            nanosecond,
+        })
+    }
+}
+
+impl fmt::Display for Time {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{:02}:{:02}:{:02}", self.hour, self.minute, self.second)?;
+        if self.nanosecond > 0 {
+            let fraction = format!("{:09}", self.nanosecond);
+            write!(formatter, ".{}", fraction.trim_right_matches('0'))?;
+        }
+        Ok(())
+    }
+}
+
+/// A date and a time, with the offset from UTC in minutes if it's known.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DateTime {
+    pub date: Date,
+    pub time: Time,
+    pub offset: Option<i16>,
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}T{}", self.date, self.time)?;
+        if let Some(offset) = self.offset {
+            let sign = if offset < 0 { '-' } else { '+' };
+            let offset = offset.abs();
+            write!(formatter, "{}{:02}:{:02}", sign, offset / 60, offset % 60)?;
+        }
+        Ok(())
+    }
+}
+
+/// Typed data read from a data file, shaped like the layout that describes it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Data {
+    Str(String),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Decimal(Decimal),
+    Date(Date),
+    Time(Time),
+    DateTime(DateTime),
+    /// A cell that matched a string literal, like an alternative of an `or`
+    Tag(String),
+    /// The cells of a `row` or the children of a `col`, named by their `as`
+    /// bindings
+    Record(Vec<(Option<String>, Data)>),
+    /// The repetitions of a repeated `col`, `many`, `some`, `optional` or
+    /// `repeat`
+    List(Vec<Data>),
+}
+
+/// The fields of a record, taken in order by generated code.
+pub struct Fields(vec::IntoIter<(Option<String>, Data)>);
+
+impl Fields {
+    pub fn take(&mut self) -> Result<Data, Error> {
+        match self.0.next() {
+            Some((_, data)) => Ok(data),
+            None => Err(DataShapeError(
+                "another field".to_owned(),
+                "the end of a record".to_owned(),
+            ).into()),
+        }
+    }
+}
+
+fn shape_error<T>(expected: &str, found: &Data) -> Result<T, Error> {
+    Err(DataShapeError(expected.to_owned(), format!("{:?}", found)).into())
+}
+
+/// Conversions into Rust types, used by the code generated from schemas.
+impl Data {
+    pub fn into_string(self) -> Result<String, Error> {
+        match self {
+            Data::Str(string) | Data::Tag(string) => Ok(string),
+            data => shape_error("a string", &data),
+        }
+    }
+
+    pub fn into_tag(self) -> Result<String, Error> {
+        match self {
+            Data::Tag(tag) => Ok(tag),
+            data => shape_error("a tag", &data),
+        }
+    }
+
+    pub fn into_int(self) -> Result<i64, Error> {
+        match self {
+            Data::Int(int) => Ok(int),
+            data => shape_error("an int", &data),
+        }
+    }
+
+    pub fn into_uint(self) -> Result<u64, Error> {
+        match self {
+            Data::UInt(uint) => Ok(uint),
+            data => shape_error("a uint", &data),
+        }
+    }
+
+    pub fn into_float(self) -> Result<f64, Error> {
+        match self {
+            Data::Float(float) => Ok(float),
+            data => shape_error("a float", &data),
+        }
+    }
+
+    pub fn into_decimal(self) -> Result<Decimal, Error> {
+        match self {
+            Data::Decimal(decimal) => Ok(decimal),
+            data => shape_error("a decimal", &data),
+        }
+    }
+
+    pub fn into_date(self) -> Result<Date, Error> {
+        match self {
+            Data::Date(date) => Ok(date),
+            data => shape_error("a date", &data),
+        }
+    }
+
+    pub fn into_time(self) -> Result<Time, Error> {
+        match self {
+            Data::Time(time) => Ok(time),
+            data => shape_error("a time", &data),
+        }
+    }
+
+    pub fn into_datetime(self) -> Result<DateTime, Error> {
+        match self {
+            Data::DateTime(datetime) => Ok(datetime),
+            data => shape_error("a date and time", &data),
+        }
+    }
+
+    pub fn into_fields(self) -> Result<Fields, Error> {
+        match self {
+            Data::Record(fields) => Ok(Fields(fields.into_iter())),
+            data => shape_error("a record", &data),
+        }
+    }
+
+    pub fn into_vec<T, F>(self, convert: F) -> Result<Vec<T>, Error>
+    where
+        F: Fn(Data) -> Result<T, Error>,
+    {
+        match self {
+            Data::List(items) => items.into_iter().map(convert).collect(),
+            data => shape_error("a list", &data),
+        }
+    }
+
+    /// Converts a list of at most one item.
+    pub fn into_option<T, F>(self, convert: F) -> Result<Option<T>, Error>
+    where
+        F: Fn(Data) -> Result<T, Error>,
+    {
+        let mut items = self.into_vec(convert)?;
+        match items.len() {
+            0 | 1 => Ok(items.pop()),
+            len => {
+                Err(DataShapeError("at most one item".to_owned(), format!("{} items", len)).into())
+            }
+        }
+    }
+
+    /// The field bound to `name`, if this is a record.
+    pub fn field(&self, name: &str) -> Option<&Data> {
+        match self {
+            Data::Record(fields) => fields
+                .iter()
+                .find(|(field, _)| field.as_ref().map(String::as_str) == Some(name))
+                .map(|(_, data)| data),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn test_convert() {
+    let int = CellType::parse("int").unwrap();
+    assert_eq!(int.convert("+12"), Some(Data::Int(12)));
+    assert_eq!(int.convert("-3"), Some(Data::Int(-3)));
+    assert_eq!(int.convert("+-3"), None);
+    assert_eq!(CellType::parse("uint").unwrap().convert("99999999999999999999"), None);
+    assert_eq!(CellType::parse("uint").unwrap().convert("+1"), None);
+
+    let float = CellType::parse("float").unwrap();
+    assert_eq!(float.convert(".5"), Some(Data::Float(0.5)));
+    assert_eq!(float.convert("-1e3"), Some(Data::Float(-1000.0)));
+    assert_eq!(float.convert("inf"), None);
+    assert_eq!(float.convert("."), None);
+
+    let decimal = CellType::parse("decimal").unwrap();
+    let convert_decimal = |cell| match decimal.convert(cell) {
+        Some(Data::Decimal(decimal)) => Some(decimal.to_string()),
+        _ => None,
+    };
+    assert_eq!(convert_decimal("12.50"), Some("12.50".to_owned()));
+    assert_eq!(convert_decimal("-.05"), Some("-0.05".to_owned()));
+    assert_eq!(convert_decimal("7."), Some("7".to_owned()));
+    assert_eq!(convert_decimal("1e3"), None);
+
+    assert!(CellType::parse("number").is_err());
+}
+
+#[test]
+fn test_constraints() {
+    let score = CellType::constrained("int", &[0, 10]).unwrap();
+    assert_eq!(score.to_string(), "int(0 10)");
+    assert_eq!(score.convert("10"), Some(Data::Int(10)));
+    assert_eq!(score.convert("11"), None);
+    assert_eq!(score.explain("11").unwrap(), "11 is out of the range 0..10");
+    assert_eq!(score.explain("eleven"), None);
+
+    let float = CellType::constrained("float", &[2]).unwrap();
+    assert_eq!(float.convert("0.0093"), Some(Data::Float(0.0093)));
+    assert_eq!(float.explain("9.35").unwrap(), "9.35 has more than 2 significant digits");
+
+    let decimal = CellType::constrained("decimal", &[1]).unwrap();
+    assert!(decimal.convert("5.5").is_some());
+    assert_eq!(
+        decimal.explain("5.55").unwrap(),
+        "5.55 has more than 1 digits after the decimal point"
+    );
+
+    assert!(CellType::constrained("int", &[10, 0]).is_err());
+    assert!(CellType::constrained("uint", &[-1, 0]).is_err());
+    assert!(CellType::constrained("float", &[0]).is_err());
+    assert!(CellType::constrained("decimal", &[1, 2]).is_err());
+    assert_eq!(
+        CellType::constrained("int", &[10, 0]).unwrap_err().to_string(),
+        "Invalid constraint int(10 0): the minimum is greater than the maximum."
+    );
+}
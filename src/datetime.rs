@@ -0,0 +1,464 @@
+use failure::Error;
+
+use data::{Data, Date, DateTime, Time};
+use errors::InvalidFormatError;
+use span::Snippet;
+
+/// An era of the Japanese calendar and the day it started on. The dates
+/// before 1873 are of the proleptic Gregorian calendar.
+struct Era {
+    name: &'static str,
+    start: Date,
+}
+
+const ERAS: &[Era] = &[
+    Era {
+        name: "明治",
+        start: Date {
+            year: 1868,
+            month: 10,
+            day: 23,
+        },
+    },
+    Era {
+        name: "大正",
+        start: Date {
+            year: 1912,
+            month: 7,
+            day: 30,
+        },
+    },
+    Era {
+        name: "昭和",
+        start: Date {
+            year: 1926,
+            month: 12,
+            day: 25,
+        },
+    },
+    Era {
+        name: "平成",
+        start: Date {
+            year: 1989,
+            month: 1,
+            day: 8,
+        },
+    },
+    Era {
+        name: "令和",
+        start: Date {
+            year: 2019,
+            month: 5,
+            day: 1,
+        },
+    },
+];
+
+/// The first year of an era is written as 元 instead of 1.
+const FIRST_ERA_YEAR: &str = "元";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Directive {
+    Literal(char),
+    /// `%Y`: a year of four digits
+    Year,
+    /// `%m`, or `%-m` if it may be written with a single digit
+    Month { padded: bool },
+    Day { padded: bool },
+    Hour { padded: bool },
+    Minute,
+    Second,
+    /// `%.f`: an optional fraction of a second, after a `.` or a `,`
+    Fraction,
+    /// `%z`: an offset from UTC like `+09:00`, `+0900` or `Z`
+    Offset,
+    /// `%EC`: the name of a Japanese era, like 平成
+    Era,
+    /// `%Ey`: the year of a Japanese era
+    EraYear,
+}
+
+/// What the cells of a format are converted into.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FormatKind {
+    Date,
+    Time,
+    DateTime,
+}
+
+/// A strftime-style format of dates and times, like `%Y-%m-%d`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DateFormat {
+    directives: Vec<Directive>,
+    kind: FormatKind,
+}
+
+/// The fields of a cell read by a format, before they're checked against
+/// the calendar.
+#[derive(Default)]
+struct Fields {
+    year: Option<i32>,
+    month: Option<u8>,
+    day: Option<u8>,
+    era: Option<&'static Era>,
+    era_year: Option<i32>,
+    hour: Option<u8>,
+    minute: Option<u8>,
+    second: Option<u8>,
+    nanosecond: u32,
+    offset: Option<i16>,
+}
+
+impl DateFormat {
+    pub fn parse(pattern: &str) -> Result<DateFormat, Error> {
+        let invalid = |reason: String| InvalidFormatError(pattern.to_owned(), reason, Snippet::default());
+
+        let mut directives = Vec::new();
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                directives.push(Directive::Literal(c));
+                continue;
+            }
+            let directive = match chars.next() {
+                Some('%') => Directive::Literal('%'),
+                Some('Y') => Directive::Year,
+                Some('m') => Directive::Month { padded: true },
+                Some('d') => Directive::Day { padded: true },
+                Some('H') => Directive::Hour { padded: true },
+                Some('M') => Directive::Minute,
+                Some('S') => Directive::Second,
+                Some('z') => Directive::Offset,
+                Some('-') => match chars.next() {
+                    Some('m') => Directive::Month { padded: false },
+                    Some('d') => Directive::Day { padded: false },
+                    Some('H') => Directive::Hour { padded: false },
+                    other => return Err(invalid(unknown_directive("-", other)).into()),
+                },
+                Some('.') => match chars.next() {
+                    Some('f') => Directive::Fraction,
+                    other => return Err(invalid(unknown_directive(".", other)).into()),
+                },
+                Some('E') => match chars.next() {
+                    Some('C') => Directive::Era,
+                    Some('y') => Directive::EraYear,
+                    other => return Err(invalid(unknown_directive("E", other)).into()),
+                },
+                other => return Err(invalid(unknown_directive("", other)).into()),
+            };
+            directives.push(directive);
+        }
+
+        let has = |wanted: fn(&Directive) -> bool| directives.iter().any(wanted);
+        let year = has(|d| *d == Directive::Year);
+        let era = has(|d| *d == Directive::Era);
+        let era_year = has(|d| *d == Directive::EraYear);
+        let month = has(|d| match d {
+            Directive::Month { .. } => true,
+            _ => false,
+        });
+        let day = has(|d| match d {
+            Directive::Day { .. } => true,
+            _ => false,
+        });
+        let hour = has(|d| match d {
+            Directive::Hour { .. } => true,
+            _ => false,
+        });
+        let minute = has(|d| *d == Directive::Minute);
+        let time_only = has(|d| match d {
+            Directive::Second | Directive::Fraction | Directive::Offset => true,
+            _ => false,
+        });
+
+        if era != era_year || (year && era) {
+            return Err(invalid("%EC and %Ey go together instead of %Y".to_owned()).into());
+        }
+        let any_date = year || era || month || day;
+        let date = (year || era) && month && day;
+        let any_time = hour || minute || time_only;
+        let time = hour && minute;
+        if any_date && !date {
+            return Err(invalid("a date needs a year, a month and a day".to_owned()).into());
+        }
+        if any_time && !time {
+            return Err(invalid("a time needs an hour and a minute".to_owned()).into());
+        }
+        let kind = match (date, time) {
+            (true, true) => FormatKind::DateTime,
+            (true, false) => FormatKind::Date,
+            (false, true) => FormatKind::Time,
+            (false, false) => {
+                return Err(invalid("a format needs a date or a time".to_owned()).into())
+            }
+        };
+
+        Ok(DateFormat { directives, kind })
+    }
+
+    pub fn kind(&self) -> FormatKind {
+        self.kind
+    }
+
+    /// Converts a cell, or returns `None` if it isn't written in the format
+    /// or isn't a date or time of the calendar.
+    pub fn convert(&self, cell: &str) -> Option<Data> {
+        self.read(cell)?.build(self.kind).ok()
+    }
+
+    /// Explains why a cell that is written in the format isn't a date or
+    /// time of the calendar, like `month 2 of 2018 has 28 days`.
+    pub fn explain(&self, cell: &str) -> Option<String> {
+        self.read(cell)?.build(self.kind).err()
+    }
+
+    fn read(&self, cell: &str) -> Option<Fields> {
+        let mut fields = Fields::default();
+        let mut rest = cell;
+        for directive in &self.directives {
+            rest = match *directive {
+                Directive::Literal(c) if rest.starts_with(c) => &rest[c.len_utf8()..],
+                Directive::Literal(_) => return None,
+                Directive::Year => {
+                    let (year, rest) = digits(rest, 4, 4)?;
+                    fields.year = Some(year as i32);
+                    rest
+                }
+                Directive::Month { padded } => {
+                    let (month, rest) = digits(rest, if padded { 2 } else { 1 }, 2)?;
+                    fields.month = Some(month as u8);
+                    rest
+                }
+                Directive::Day { padded } => {
+                    let (day, rest) = digits(rest, if padded { 2 } else { 1 }, 2)?;
+                    fields.day = Some(day as u8);
+                    rest
+                }
+                Directive::Hour { padded } => {
+                    let (hour, rest) = digits(rest, if padded { 2 } else { 1 }, 2)?;
+                    fields.hour = Some(hour as u8);
+                    rest
+                }
+                Directive::Minute => {
+                    let (minute, rest) = digits(rest, 2, 2)?;
+                    fields.minute = Some(minute as u8);
+                    rest
+                }
+                Directive::Second => {
+                    let (second, rest) = digits(rest, 2, 2)?;
+                    fields.second = Some(second as u8);
+                    rest
+                }
+                Directive::Fraction if rest.starts_with('.') || rest.starts_with(',') => {
+                    let len = rest[1..].bytes().take_while(u8::is_ascii_digit).count();
+                    if len < 1 || len > 9 {
+                        return None;
+                    }
+                    let fraction = format!("{:0<9}", &rest[1..len + 1]);
+                    fields.nanosecond = fraction.parse().ok()?;
+                    &rest[len + 1..]
+                }
+                Directive::Fraction => rest,
+                Directive::Offset if rest.starts_with('Z') => {
+                    fields.offset = Some(0);
+                    &rest[1..]
+                }
+                Directive::Offset => {
+                    let sign = match rest.chars().next()? {
+                        '+' => 1,
+                        '-' => -1,
+                        _ => return None,
+                    };
+                    let (hours, after_hours) = digits(&rest[1..], 2, 2)?;
+                    let after_colon = after_hours.trim_left_matches(':');
+                    if after_hours.len() - after_colon.len() > 1 {
+                        return None;
+                    }
+                    let (minutes, rest) = digits(after_colon, 2, 2)?;
+                    if hours > 23 || minutes > 59 {
+                        return None;
+                    }
+                    fields.offset = Some(sign * (hours * 60 + minutes) as i16);
+                    rest
+                }
+                Directive::Era => {
+                    let era = ERAS.iter().find(|era| rest.starts_with(era.name))?;
+                    fields.era = Some(era);
+                    &rest[era.name.len()..]
+                }
+                Directive::EraYear if rest.starts_with(FIRST_ERA_YEAR) => {
+                    fields.era_year = Some(1);
+                    &rest[FIRST_ERA_YEAR.len()..]
+                }
+                Directive::EraYear => {
+                    let (year, rest) = digits(rest, 1, 2)?;
+                    fields.era_year = Some(year as i32);
+                    rest
+                }
+            };
+        }
+        if rest.is_empty() {
+            Some(fields)
+        } else {
+            None
+        }
+    }
+}
+
+fn unknown_directive(prefix: &str, next: Option<char>) -> String {
+    match next {
+        Some(c) => format!("unknown directive %{}{}", prefix, c),
+        None => "the format ends with an unfinished directive".to_owned(),
+    }
+}
+
+/// Reads a number of `min` to `max` digits from the start of `text`, and
+/// returns it along with the rest of the text.
+fn digits(text: &str, min: usize, max: usize) -> Option<(u32, &str)> {
+    let len = text.bytes().take(max).take_while(u8::is_ascii_digit).count();
+    if len < min {
+        return None;
+    }
+    Some((text[..len].parse().ok()?, &text[len..]))
+}
+
+impl Fields {
+    fn date(&self) -> Result<Date, String> {
+        let month = self.month.expect("Invariant: checked by the format.");
+        let day = self.day.expect("Invariant: checked by the format.");
+        let era = match self.era {
+            Some(era) => era,
+            None => {
+                let year = self.year.expect("Invariant: checked by the format.");
+                return Date::new(year, month, day);
+            }
+        };
+
+        let era_year = self.era_year.expect("Invariant: checked by the format.");
+        if era_year < 1 {
+            return Err(format!("{} has no year {}", era.name, era_year));
+        }
+        let date = Date::new(era.start.year + era_year - 1, month, day)?;
+        let next = ERAS
+            .iter()
+            .skip_while(|other| other.name != era.name)
+            .nth(1);
+        if date < era.start || next.map_or(false, |next| date >= next.start) {
+            return Err(format!("{} is not in the era {}", date, era.name));
+        }
+        Ok(date)
+    }
+
+    fn time(&self) -> Result<Time, String> {
+        Time::new(
+            self.hour.expect("Invariant: checked by the format."),
+            self.minute.expect("Invariant: checked by the format."),
+            self.second.unwrap_or(0),
+            self.nanosecond,
+        )
+    }
+
+    /// Checks the fields against the calendar.
+    fn build(&self, kind: FormatKind) -> Result<Data, String> {
+        match kind {
+            FormatKind::Date => self.date().map(Data::Date),
+            FormatKind::Time => self.time().map(Data::Time),
+            FormatKind::DateTime => Ok(Data::DateTime(DateTime {
+                date: self.date()?,
+                time: self.time()?,
+                offset: self.offset,
+            })),
+        }
+    }
+}
+
+#[test]
+fn test_parse_format() {
+    assert_eq!(DateFormat::parse("%Y-%m-%d").unwrap().kind(), FormatKind::Date);
+    assert_eq!(DateFormat::parse("%H:%M").unwrap().kind(), FormatKind::Time);
+    assert_eq!(
+        DateFormat::parse("%Y-%m-%dT%H:%M:%S%.f%z").unwrap().kind(),
+        FormatKind::DateTime
+    );
+    assert_eq!(
+        DateFormat::parse("%EC%Ey年%-m月%-d日").unwrap().kind(),
+        FormatKind::Date
+    );
+
+    let message = |pattern| DateFormat::parse(pattern).unwrap_err().to_string();
+    assert_eq!(
+        message("%Y-%q"),
+        "Invalid date format \"%Y-%q\": unknown directive %q."
+    );
+    assert_eq!(
+        message("%Y-%m"),
+        "Invalid date format \"%Y-%m\": a date needs a year, a month and a day."
+    );
+    assert!(DateFormat::parse("%H:%S").is_err());
+    assert!(DateFormat::parse("%Ey/%m/%d").is_err());
+    assert!(DateFormat::parse("%Y%").is_err());
+    assert!(DateFormat::parse("plain").is_err());
+}
+
+#[test]
+fn test_convert_dates() {
+    let date = |year, month, day| Some(Data::Date(Date { year, month, day }));
+
+    let iso = DateFormat::parse("%Y-%m-%d").unwrap();
+    assert_eq!(iso.convert("2018-06-20"), date(2018, 6, 20));
+    assert_eq!(iso.convert("2018-6-20"), None);
+    assert_eq!(iso.convert("2018-02-30"), None);
+    assert_eq!(iso.explain("2018-02-30").unwrap(), "month 2 of 2018 has 28 days");
+    assert_eq!(iso.convert("2016-02-29"), date(2016, 2, 29));
+    assert_eq!(iso.explain("2018-6-20"), None);
+
+    let slash = DateFormat::parse("%Y/%-m/%-d").unwrap();
+    assert_eq!(slash.convert("2018/6/20"), date(2018, 6, 20));
+    assert_eq!(slash.convert("2018/06/20"), date(2018, 6, 20));
+
+    let era = DateFormat::parse("%EC%Ey年%-m月%-d日").unwrap();
+    assert_eq!(era.convert("平成30年6月20日"), date(2018, 6, 20));
+    assert_eq!(era.convert("令和元年5月1日"), date(2019, 5, 1));
+    assert_eq!(era.convert("平成31年4月30日"), date(2019, 4, 30));
+    assert_eq!(
+        era.explain("平成31年5月1日").unwrap(),
+        "2019-05-01 is not in the era 平成"
+    );
+    assert_eq!(era.convert("天平1年1月1日"), None);
+}
+
+#[test]
+fn test_convert_times() {
+    let time = DateFormat::parse("%H:%M:%S%.f").unwrap();
+    assert_eq!(
+        time.convert("09:30:00.25"),
+        Some(Data::Time(Time {
+            hour: 9,
+            minute: 30,
+            second: 0,
+            nanosecond: 250_000_000,
+        }))
+    );
+    assert!(time.convert("23:59:60").is_some());
+    assert_eq!(time.explain("24:00:00").unwrap(), "24 is not an hour");
+    assert_eq!(time.convert("09:30:00."), None);
+
+    let datetime = DateFormat::parse("%Y-%m-%dT%H:%M:%S%z").unwrap();
+    let converted = |cell| match datetime.convert(cell) {
+        Some(Data::DateTime(datetime)) => Some(datetime.to_string()),
+        _ => None,
+    };
+    assert_eq!(
+        converted("2018-06-20T09:30:00+09:00"),
+        Some("2018-06-20T09:30:00+09:00".to_owned())
+    );
+    assert_eq!(
+        converted("2018-06-20T09:30:00-0330"),
+        Some("2018-06-20T09:30:00-03:30".to_owned())
+    );
+    assert_eq!(
+        converted("2018-06-20T09:30:00Z"),
+        Some("2018-06-20T09:30:00+00:00".to_owned())
+    );
+    assert_eq!(converted("2018-06-20T09:30:00"), None);
+}
@@ -0,0 +1,532 @@
+use std::fmt;
+use std::io::BufRead;
+use std::mem;
+use std::str::from_utf8;
+
+use failure::Error;
+
+use errors::{EncodingError, InvalidSettingError};
+use span::Snippet;
+use validate::{LineReport, Mismatch};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    /// Either `\n` or `\r\n`
+    Any,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Ascii,
+}
+
+/// A setting of a `dialect`, like `delimiter(",")`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Setting {
+    Delimiter(char),
+    Quote(Option<char>),
+    LineEnding(LineEnding),
+    Encoding(Encoding),
+    Header(bool),
+    /// The widths of fixed-width columns in characters, like `widths("10 4 8")`
+    Widths(Vec<usize>),
+}
+
+fn single_char(value: &str) -> Option<char> {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+impl Setting {
+    /// Parses the value of the setting intrinsic `name`.
+    pub fn parse(name: &str, value: &str) -> Result<Setting, Error> {
+        let setting = match name {
+            "delimiter" => single_char(value)
+                .filter(|&c| c != '\n' && c != '\r')
+                .map(Setting::Delimiter),
+            "quote" if value.is_empty() => Some(Setting::Quote(None)),
+            "quote" => single_char(value).map(|c| Setting::Quote(Some(c))),
+            "line_ending" => match value {
+                "\n" => Some(Setting::LineEnding(LineEnding::Lf)),
+                "\r\n" => Some(Setting::LineEnding(LineEnding::CrLf)),
+                "any" => Some(Setting::LineEnding(LineEnding::Any)),
+                _ => None,
+            },
+            "encoding" => match value.to_lowercase().as_str() {
+                "utf-8" | "utf8" => Some(Setting::Encoding(Encoding::Utf8)),
+                "ascii" | "us-ascii" => Some(Setting::Encoding(Encoding::Ascii)),
+                _ => None,
+            },
+            "header" => match value {
+                "present" => Some(Setting::Header(true)),
+                "absent" => Some(Setting::Header(false)),
+                _ => None,
+            },
+            "widths" => value
+                .split_whitespace()
+                .map(|width| width.parse().ok().filter(|&width| width > 0))
+                .collect::<Option<Vec<_>>>()
+                .filter(|widths| !widths.is_empty())
+                .map(Setting::Widths),
+            _ => None,
+        };
+
+        setting.ok_or_else(|| {
+            InvalidSettingError(name.to_owned(), value.to_owned(), Snippet::default()).into()
+        })
+    }
+}
+
+impl fmt::Display for Setting {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Setting::Delimiter(c) => write!(formatter, "delimiter({:?})", c.to_string()),
+            Setting::Quote(Some(c)) => write!(formatter, "quote({:?})", c.to_string()),
+            Setting::Quote(None) => write!(formatter, "quote(\"\")"),
+            Setting::LineEnding(LineEnding::Lf) => write!(formatter, "line_ending(\"\\n\")"),
+            Setting::LineEnding(LineEnding::CrLf) => write!(formatter, "line_ending(\"\\r\\n\")"),
+            Setting::LineEnding(LineEnding::Any) => write!(formatter, "line_ending(\"any\")"),
+            Setting::Encoding(Encoding::Utf8) => write!(formatter, "encoding(\"utf-8\")"),
+            Setting::Encoding(Encoding::Ascii) => write!(formatter, "encoding(\"ascii\")"),
+            Setting::Header(true) => write!(formatter, "header(\"present\")"),
+            Setting::Header(false) => write!(formatter, "header(\"absent\")"),
+            Setting::Widths(widths) => {
+                let widths = widths.iter().map(usize::to_string).collect::<Vec<_>>();
+                write!(formatter, "widths({:?})", widths.join(" "))
+            }
+        }
+    }
+}
+
+/// How the cells and lines of a data file are written. A dialect without
+/// settings is TSV without quoting.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Dialect {
+    pub delimiter: char,
+    pub quote: Option<char>,
+    pub line_ending: LineEnding,
+    pub encoding: Encoding,
+    /// Whether the first line is a header that isn't described by the layout
+    pub header: bool,
+    /// The widths of the columns if the cells are padded into fixed-width
+    /// columns instead of being delimited
+    pub widths: Option<Vec<usize>>,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Dialect {
+            delimiter: '\t',
+            quote: None,
+            line_ending: LineEnding::Any,
+            encoding: Encoding::Utf8,
+            header: false,
+            widths: None,
+        }
+    }
+}
+
+impl Dialect {
+    /// Comma-separated values as specified by RFC 4180.
+    pub fn csv() -> Self {
+        Dialect {
+            delimiter: ',',
+            quote: Some('"'),
+            ..Dialect::default()
+        }
+    }
+
+    pub fn with_settings(settings: &[Setting]) -> Self {
+        let mut dialect = Dialect::default();
+        for setting in settings {
+            match *setting {
+                Setting::Delimiter(c) => dialect.delimiter = c,
+                Setting::Quote(quote) => dialect.quote = quote,
+                Setting::LineEnding(line_ending) => dialect.line_ending = line_ending,
+                Setting::Encoding(encoding) => dialect.encoding = encoding,
+                Setting::Header(header) => dialect.header = header,
+                Setting::Widths(ref widths) => dialect.widths = Some(widths.clone()),
+            }
+        }
+        dialect
+    }
+
+    fn settings(&self) -> Vec<Setting> {
+        let mut settings = vec![
+            Setting::Delimiter(self.delimiter),
+            Setting::Quote(self.quote),
+            Setting::LineEnding(self.line_ending),
+            Setting::Encoding(self.encoding),
+            Setting::Header(self.header),
+        ];
+        settings.extend(self.widths.clone().map(Setting::Widths));
+        settings
+    }
+
+    /// Splits the data into records of cells. The records that can't be read
+    /// properly are reported as mismatches, but they are still included with
+    /// the cells that were read.
+    pub fn records(&self, data: &str) -> Result<(Vec<Record>, Vec<LineReport>), Error> {
+        let mut reader = RecordReader::new(self.clone(), data.as_bytes());
+        let records = reader.by_ref().collect::<Result<Vec<_>, _>>()?;
+        Ok((records, reader.take_problems()))
+    }
+
+    /// Decodes a line of a data file.
+    fn decode<'d>(&self, data: &'d [u8], line: usize) -> Result<&'d str, Error> {
+        let invalid = || EncodingError(Setting::Encoding(self.encoding).to_string(), line);
+
+        let text = from_utf8(data).map_err(|_| invalid())?;
+        if self.encoding == Encoding::Ascii && !text.is_ascii() {
+            return Err(invalid().into());
+        }
+        Ok(text)
+    }
+
+    /// Reads the cells of a line into the record in progress, returning whether
+    /// the record is complete.
+    fn read_line(&self, line: &str, ending: &str, state: &mut RecordState) -> bool {
+        if let Some(ref widths) = self.widths {
+            read_fixed_width(line, widths, state);
+            return true;
+        }
+
+        for c in line.chars() {
+            if state.in_quotes {
+                if Some(c) == self.quote {
+                    state.in_quotes = false;
+                    state.after_quote = true;
+                } else {
+                    state.cell.push(c);
+                }
+            } else if state.after_quote && Some(c) == self.quote {
+                // A doubled quote inside a quoted cell
+                state.cell.push(c);
+                state.in_quotes = true;
+                state.after_quote = false;
+            } else if c == self.delimiter {
+                state.end_cell();
+            } else if state.field_start && Some(c) == self.quote {
+                state.in_quotes = true;
+                state.field_start = false;
+            } else if state.after_quote {
+                // Something else than a delimiter after a closing quote
+                state.error = state.error.take().or(Some(Mismatch::Quoting));
+                state.cell.push(c);
+            } else {
+                state.cell.push(c);
+                state.field_start = false;
+            }
+        }
+
+        if state.in_quotes {
+            state.cell.push_str(ending);
+            false
+        } else {
+            state.end_cell();
+            true
+        }
+    }
+}
+
+/// Cuts a line into cells of fixed widths, trimming the spaces that pad them.
+/// The columns that a narrower line doesn't reach are empty cells, since the
+/// trailing padding is often left out. The rest of a line that is wider than
+/// the columns is an extra cell.
+fn read_fixed_width(line: &str, widths: &[usize], state: &mut RecordState) {
+    let mut rest = line;
+    for &width in widths {
+        let end = rest.char_indices().nth(width).map_or(rest.len(), |(end, _)| end);
+        state.cell.push_str(rest[..end].trim_matches(' '));
+        state.end_cell();
+        rest = &rest[end..];
+    }
+    if !rest.is_empty() {
+        state.cell.push_str(rest.trim_matches(' '));
+        state.end_cell();
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("dialect(")?;
+        for (idx, setting) in self.settings().iter().enumerate() {
+            if idx > 0 {
+                formatter.write_str(" ")?;
+            }
+            write!(formatter, "{}", setting)?;
+        }
+        formatter.write_str(")")
+    }
+}
+
+/// Reads the records of a data file one at a time, so that files of any size
+/// can be read in bounded memory.
+pub struct RecordReader<R> {
+    dialect: Dialect,
+    reader: R,
+    /// The number of lines read so far
+    line: usize,
+    buffer: Vec<u8>,
+    problems: Vec<LineReport>,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    pub fn new(dialect: Dialect, reader: R) -> Self {
+        RecordReader {
+            dialect,
+            reader,
+            line: 0,
+            buffer: Vec::new(),
+            problems: Vec::new(),
+        }
+    }
+
+    /// The number of lines read so far.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Takes the problems found in the records read so far. The records that
+    /// can't be read properly are reported as mismatches, but they are still
+    /// returned with the cells that were read.
+    pub fn take_problems(&mut self) -> Vec<LineReport> {
+        mem::replace(&mut self.problems, Vec::new())
+    }
+
+    /// Reads the next record, or returns `None` at the end of the data.
+    pub fn read_record(&mut self) -> Result<Option<Record>, Error> {
+        let dialect = &self.dialect;
+        let mut state = RecordState::new(self.line + 1);
+
+        loop {
+            self.buffer.clear();
+            if self.reader.read_until(b'\n', &mut self.buffer)? == 0 {
+                if state.line > self.line {
+                    return Ok(None);
+                }
+                // The data ended inside a quoted cell
+                state.error = Some(Mismatch::Quoting);
+                state.end_cell();
+                return Ok(Some(state.finish(&mut self.problems)));
+            }
+            self.line += 1;
+
+            let terminated = self.buffer.last() == Some(&b'\n');
+            if terminated {
+                self.buffer.pop();
+            }
+            let line = dialect.decode(&self.buffer, self.line)?;
+            let (line, crlf) = if line.ends_with('\r') && dialect.line_ending != LineEnding::Lf {
+                (&line[..line.len() - 1], true)
+            } else {
+                (line, false)
+            };
+
+            let wrong_ending = terminated && match dialect.line_ending {
+                LineEnding::Lf => line.ends_with('\r'),
+                LineEnding::CrLf => !crlf,
+                LineEnding::Any => false,
+            };
+            if wrong_ending {
+                state.error = state.error.take().or(Some(Mismatch::LineEnding));
+            }
+
+            let ending = if crlf { "\r\n" } else { "\n" };
+            if dialect.read_line(line, ending, &mut state) {
+                return Ok(Some(state.finish(&mut self.problems)));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for RecordReader<R> {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_record() {
+            Ok(record) => record.map(Ok),
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+/// The cells of a line, or of several lines if there are line breaks inside
+/// quoted cells. `line` is the number of the first line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub line: usize,
+    pub cells: Vec<String>,
+}
+
+struct RecordState {
+    line: usize,
+    cells: Vec<String>,
+    cell: String,
+    field_start: bool,
+    in_quotes: bool,
+    after_quote: bool,
+    error: Option<Mismatch>,
+}
+
+impl RecordState {
+    fn new(line: usize) -> Self {
+        RecordState {
+            line,
+            cells: Vec::new(),
+            cell: String::new(),
+            field_start: true,
+            in_quotes: false,
+            after_quote: false,
+            error: None,
+        }
+    }
+
+    fn end_cell(&mut self) {
+        self.cells.push(mem::replace(&mut self.cell, String::new()));
+        self.field_start = true;
+        self.after_quote = false;
+    }
+
+    fn finish(self, problems: &mut Vec<LineReport>) -> Record {
+        if let Some(mismatch) = self.error {
+            problems.push(LineReport {
+                line: self.line,
+                mismatch,
+            });
+        }
+        Record {
+            line: self.line,
+            cells: self.cells,
+        }
+    }
+}
+
+#[cfg(test)]
+fn cells(records: &[Record]) -> Vec<Vec<&str>> {
+    records
+        .iter()
+        .map(|record| record.cells.iter().map(String::as_str).collect())
+        .collect()
+}
+
+#[test]
+fn test_read_tsv() {
+    let dialect = Dialect::default();
+
+    assert!(dialect.records("").unwrap().0.is_empty());
+    assert_eq!(
+        cells(&dialect.records("a\tb\r\n\"c\"\td\n\n").unwrap().0),
+        vec![vec!["a", "b"], vec!["\"c\"", "d"], vec![""]]
+    );
+}
+
+#[test]
+fn test_read_csv() {
+    let dialect = Dialect::csv();
+
+    let (records, problems) =
+        dialect.records("a,\"b,c\"\n\"multi\r\nline\",\"\"\"quoted\"\"\"\r\nlast,\n").unwrap();
+    assert!(problems.is_empty());
+    assert_eq!(
+        cells(&records),
+        vec![
+            vec!["a", "b,c"],
+            vec!["multi\r\nline", "\"quoted\""],
+            vec!["last", ""],
+        ]
+    );
+    assert_eq!(
+        records.iter().map(|r| r.line).collect::<Vec<_>>(),
+        vec![1, 2, 4]
+    );
+
+    let (records, problems) = dialect.records("a,\"b\"c\nd,\"unterminated\n").unwrap();
+    assert_eq!(cells(&records), vec![vec!["a", "bc"], vec!["d", "unterminated\n"]]);
+    assert_eq!(
+        problems,
+        vec![
+            LineReport {
+                line: 1,
+                mismatch: Mismatch::Quoting,
+            },
+            LineReport {
+                line: 2,
+                mismatch: Mismatch::Quoting,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_read_fixed_width() {
+    let dialect = Dialect::with_settings(&[Setting::Widths(vec![4, 3])]);
+
+    let (records, problems) = dialect.records("ab   12\näö  3\r\nxyz\nabcd123rest\n").unwrap();
+    assert!(problems.is_empty());
+    assert_eq!(
+        cells(&records),
+        vec![
+            vec!["ab", "12"],
+            vec!["äö", "3"],
+            vec!["xyz", ""],
+            vec!["abcd", "123", "rest"],
+        ]
+    );
+}
+
+#[test]
+fn test_line_endings() {
+    let dialect = Dialect::with_settings(&[Setting::LineEnding(LineEnding::CrLf)]);
+
+    let (records, problems) = dialect.records("a\r\nb\nc").unwrap();
+    assert_eq!(records.len(), 3);
+    assert_eq!(
+        problems,
+        vec![LineReport {
+            line: 2,
+            mismatch: Mismatch::LineEnding,
+        }]
+    );
+}
+
+#[test]
+fn test_settings() {
+    assert_eq!(Setting::parse("delimiter", ",").unwrap(), Setting::Delimiter(','));
+    assert_eq!(Setting::parse("quote", "").unwrap(), Setting::Quote(None));
+    assert_eq!(Setting::parse("header", "present").unwrap(), Setting::Header(true));
+    assert!(Setting::parse("delimiter", ",,").is_err());
+    assert!(Setting::parse("encoding", "latin-1").is_err());
+    assert_eq!(Setting::parse("widths", " 10 2").unwrap(), Setting::Widths(vec![10, 2]));
+    assert!(Setting::parse("widths", "").is_err());
+    assert!(Setting::parse("widths", "3 0").is_err());
+    assert!(Dialect::with_settings(&[Setting::Widths(vec![4, 3])])
+        .to_string()
+        .ends_with(r#"header("absent") widths("4 3"))"#));
+
+    assert_eq!(
+        Dialect::csv().to_string(),
+        r#"dialect(delimiter(",") quote("\"") line_ending("any") encoding("utf-8") header("absent"))"#
+    );
+}
+
+#[test]
+fn test_decode() {
+    let ascii = Dialect::with_settings(&[Setting::Encoding(Encoding::Ascii)]);
+
+    assert_eq!(ascii.records("abc").unwrap().0.len(), 1);
+    assert_eq!(
+        ascii.records("a\nä").unwrap_err().to_string(),
+        "The data isn't valid encoding(\"ascii\") at line 2."
+    );
+
+    let mut reader = RecordReader::new(Dialect::default(), &b"a\n\xff\n"[..]);
+    assert!(reader.read_record().unwrap().is_some());
+    assert!(reader.read_record().is_err());
+}
@@ -1,26 +1,64 @@
+use std::fmt;
+
+use failure::Error;
+
+use span::{SourceMap, Snippet, Span};
+
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid syntax{}", _0)]
+pub struct SyntaxError(pub Snippet);
+
+#[derive(Debug, Fail)]
+#[fail(display = "The name {} was not found in lexical scope{}", _0, _1)]
+pub struct UnknownNameError(pub String, pub Snippet);
+
+#[derive(Debug, Fail)]
+#[fail(display = "The path {} didn't lead to anywhere.{}", _0, _1)]
+pub struct PathResolutionError(pub String, pub Snippet);
+
+#[derive(Debug, Fail)]
+#[fail(display = "The export {} was invalid. Use as keyword.{}", _0, _1)]
+pub struct InvalidExportError(pub String, pub Snippet);
+
+#[derive(Debug, Fail)]
+#[fail(display = "The item {} is exported before its definition.{}", _0, _1)]
+pub struct EarlyExportError(pub String, pub Snippet);
+
+#[derive(Debug, Fail)]
+#[fail(display = "The item {} is exported twice.{}", _0, _1)]
+pub struct DuplicateExportError(pub String, pub Snippet);
+
+#[derive(Debug, Fail)]
+#[fail(display = "Can't shadow local bindings at binding {}{}", _0, _1)]
+pub struct ShadowingError(pub String, pub Snippet);
+
 #[derive(Debug, Fail)]
-#[fail(display = "Invalid syntax")]
-pub struct SyntaxError;
+#[fail(display = "Accessed item is private {}{}", _0, _1)]
+pub struct PrivacyError(pub String, pub Snippet);
 
 #[derive(Debug, Fail)]
-#[fail(display = "The name {} was not found in lexical scope", _0)]
-pub struct UnknownNameError(pub String);
+#[fail(display = "The name {} is ambiguous: it's imported from both {} and {}.{}", _0, _1, _2, _3)]
+pub struct AmbiguityError(pub String, pub String, pub String, pub Snippet);
 
 #[derive(Debug, Fail)]
-#[fail(display = "The path {} didn't lead to anywhere.", _0)]
-pub struct PathResolutionError(pub String);
+#[fail(display = "The items refer to each other in a cycle: {}.{}", _0, _1)]
+pub struct ReferenceCycleError(pub String, pub Snippet);
 
 #[derive(Debug, Fail)]
-#[fail(display = "The export {} was invalid. Use as keyword.", _0)]
-pub struct InvalidExportError(pub String);
+#[fail(display = "Import libraries by their names, like import(shapes).{}", _0)]
+pub struct InvalidImportError(pub Snippet);
 
 #[derive(Debug, Fail)]
-#[fail(display = "Can't shadow local bindings at binding {}", _0)]
-pub struct ShadowingError(pub String);
+#[fail(display = "Use declarations are only allowed at the top level of a library.{}", _0)]
+pub struct InvalidUseError(pub Snippet);
 
 #[derive(Debug, Fail)]
-#[fail(display = "Accessed item is private {}", _0)]
-pub struct PrivacyError(pub String);
+#[fail(display = "The library {} was not found in the search path.{}", _0, _1)]
+pub struct UnknownLibraryError(pub String, pub Snippet);
+
+#[derive(Debug, Fail)]
+#[fail(display = "The libraries import each other in a cycle: {}.{}", _0, _1)]
+pub struct ImportCycleError(pub String, pub Snippet);
 
 #[derive(Debug, Fail)]
 #[fail(
@@ -29,11 +67,212 @@ pub struct PrivacyError(pub String);
 pub struct InvalidLibraryFileName(pub String);
 
 #[derive(Debug, Fail)]
-#[fail(display = "Item expects {} arguments but {} was provided.", _0, _1)]
-pub struct WrongNumberOfArguments(pub usize, pub usize);
+#[fail(display = "Item expects {} arguments but {} was provided.{}", _0, _1, _2)]
+pub struct WrongNumberOfArguments(pub usize, pub usize, pub Snippet);
+
+#[derive(Debug, Fail)]
+#[fail(display = "Wrong type of arguments.{}", _0)]
+pub struct WrongTypeOfArguments(pub Snippet);
+
+/// The children of a `col` have different widths. The fields are the
+/// descriptions of the first child and of the mismatching one with their
+/// widths.
+#[derive(Fail)]
+#[fail(
+    display = "Type error: all the rows of a col must have the same width, but {} is {} cells wide and {} is {} cells wide.{}",
+    _0, _1, _2, _3, _4
+)]
+pub struct DimensionMismatch(pub String, pub i16, pub String, pub i16, pub Snippet);
+
+// The message is a part of the debug output so that it identifies the error as
+// a type error.
+impl fmt::Debug for DimensionMismatch {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "DimensionMismatch({})", self)
+    }
+}
+
+#[derive(Debug, Fail)]
+#[fail(
+    display = "Invalid repetition count {:?}. Use a number like \"3\" or a range like \"1..3\".{}",
+    _0, _1
+)]
+pub struct InvalidCountError(pub String, pub Snippet);
+
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid number {}: it doesn't fit in 64 bits.{}", _0, _1)]
+pub struct InvalidNumberError(pub String, pub Snippet);
+
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid value {:?} for the dialect setting {}.{}", _1, _0, _2)]
+pub struct InvalidSettingError(pub String, pub String, pub Snippet);
 
 #[derive(Debug, Fail)]
 #[fail(
-    display = "Wrong type of arguments.",
+    display = "Unknown cell type {:?}: expected \"int\", \"uint\", \"float\" or \"decimal\".{}",
+    _0, _1
 )]
-pub struct WrongTypeOfArguments;
+pub struct UnknownCellTypeError(pub String, pub Snippet);
+
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid date format {:?}: {}.{}", _0, _1, _2)]
+pub struct InvalidFormatError(pub String, pub String, pub Snippet);
+
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid constraint {}: {}.{}", _0, _1, _2)]
+pub struct InvalidConstraintError(pub String, pub String, pub Snippet);
+
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid regular expression {:?}: {}{}", _0, _1, _2)]
+pub struct InvalidPatternError(pub String, pub String, pub Snippet);
+
+#[derive(Debug, Fail)]
+#[fail(display = "No native library provides the intrinsic {}.{}", _0, _1)]
+pub struct MissingIntrinsicError(pub String, pub Snippet);
+
+#[derive(Debug, Fail)]
+#[fail(display = "The native intrinsic {} failed: {}{}", _0, _1, _2)]
+pub struct NativeIntrinsicError(pub String, pub String, pub Snippet);
+
+#[derive(Debug, Fail)]
+#[fail(
+    display = "The native library {} was built for ABI version {:?} but version {} is required.",
+    _0, _1, _2
+)]
+pub struct AbiVersionError(pub String, pub Option<u32>, pub u32);
+
+#[derive(Debug, Fail)]
+#[fail(display = "The library {} was not found in the library search path.", _0)]
+pub struct LibraryNotFoundError(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "The library {} doesn't export a layout named after it.", _0)]
+pub struct NoLayoutError(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "The library {} wasn't read from a file, so it can't be embedded.", _0)]
+pub struct NoSourceFileError(pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "The data isn't valid {} at line {}.", _0, _1)]
+pub struct EncodingError(pub String, pub usize);
+
+#[derive(Debug, Fail)]
+#[fail(display = "The data didn't match the schema at {} places.", _0)]
+pub struct InvalidDataError(pub usize);
+
+#[derive(Debug, Fail)]
+#[fail(display = "The data doesn't have the shape of the schema: expected {}, found {}.", _0, _1)]
+pub struct DataShapeError(pub String, pub String);
+
+#[derive(Debug, Fail)]
+#[fail(display = "The validation stopped at the limit of {} mismatches.", _0)]
+pub struct TooManyMismatchesError(pub usize);
+
+/// Maps an error to the exit code used by the command-line tool:
+/// 1 for I/O and other failures, 2 for invalid invocations, 3 for syntax errors,
+/// 4 for name resolution errors, 5 for type errors and 6 for data that doesn't
+/// match its schema.
+pub fn exit_code(err: &Error) -> i32 {
+    if err.downcast_ref::<InvalidLibraryFileName>().is_some() {
+        2
+    } else if err.downcast_ref::<SyntaxError>().is_some() {
+        3
+    } else if err.downcast_ref::<UnknownNameError>().is_some()
+        || err.downcast_ref::<PathResolutionError>().is_some()
+        || err.downcast_ref::<InvalidExportError>().is_some()
+        || err.downcast_ref::<EarlyExportError>().is_some()
+        || err.downcast_ref::<DuplicateExportError>().is_some()
+        || err.downcast_ref::<ShadowingError>().is_some()
+        || err.downcast_ref::<PrivacyError>().is_some()
+        || err.downcast_ref::<AmbiguityError>().is_some()
+        || err.downcast_ref::<ReferenceCycleError>().is_some()
+        || err.downcast_ref::<InvalidImportError>().is_some()
+        || err.downcast_ref::<InvalidUseError>().is_some()
+        || err.downcast_ref::<UnknownLibraryError>().is_some()
+        || err.downcast_ref::<ImportCycleError>().is_some()
+    {
+        4
+    } else if err.downcast_ref::<WrongNumberOfArguments>().is_some()
+        || err.downcast_ref::<WrongTypeOfArguments>().is_some()
+        || err.downcast_ref::<DimensionMismatch>().is_some()
+        || err.downcast_ref::<InvalidCountError>().is_some()
+        || err.downcast_ref::<InvalidSettingError>().is_some()
+        || err.downcast_ref::<InvalidNumberError>().is_some()
+        || err.downcast_ref::<UnknownCellTypeError>().is_some()
+        || err.downcast_ref::<InvalidConstraintError>().is_some()
+        || err.downcast_ref::<InvalidFormatError>().is_some()
+        || err.downcast_ref::<InvalidPatternError>().is_some()
+        || err.downcast_ref::<MissingIntrinsicError>().is_some()
+        || err.downcast_ref::<NativeIntrinsicError>().is_some()
+        || err.downcast_ref::<NoLayoutError>().is_some()
+    {
+        5
+    } else if err.downcast_ref::<InvalidDataError>().is_some()
+        || err.downcast_ref::<TooManyMismatchesError>().is_some()
+        || err.downcast_ref::<EncodingError>().is_some()
+    {
+        6
+    } else {
+        1
+    }
+}
+
+macro_rules! snippets {
+    ($($error:ident . $field:tt),*) => {
+        fn snippet_mut(err: &mut Error) -> Option<&mut Snippet> {
+            $(
+                if err.downcast_ref::<$error>().is_some() {
+                    return err.downcast_mut::<$error>().map(|e| &mut e.$field);
+                }
+            )*
+            None
+        }
+    };
+}
+
+snippets!(
+    SyntaxError.0,
+    UnknownNameError.1,
+    PathResolutionError.1,
+    InvalidExportError.1,
+    EarlyExportError.1,
+    DuplicateExportError.1,
+    ShadowingError.1,
+    PrivacyError.1,
+    AmbiguityError.3,
+    ReferenceCycleError.1,
+    InvalidImportError.0,
+    InvalidUseError.0,
+    UnknownLibraryError.1,
+    ImportCycleError.1,
+    WrongNumberOfArguments.2,
+    WrongTypeOfArguments.0,
+    DimensionMismatch.4,
+    InvalidCountError.1,
+    InvalidSettingError.2,
+    InvalidNumberError.1,
+    UnknownCellTypeError.1,
+    InvalidConstraintError.2,
+    InvalidFormatError.2,
+    InvalidPatternError.2,
+    MissingIntrinsicError.1,
+    NativeIntrinsicError.2
+);
+
+/// Points an error to a span of source code, unless it already points to a
+/// more specific place.
+pub fn with_span(mut err: Error, span: Span) -> Error {
+    if let Some(snippet) = snippet_mut(&mut err) {
+        snippet.set_span(span);
+    }
+    err
+}
+
+/// Renders the source snippet of an error using the source files it points to.
+pub fn locate(mut err: Error, sources: &SourceMap) -> Error {
+    if let Some(snippet) = snippet_mut(&mut err) {
+        snippet.locate(sources);
+    }
+    err
+}
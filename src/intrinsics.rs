@@ -0,0 +1,849 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+use failure::Error;
+use regex::Regex;
+
+use data::CellType;
+use datetime::DateFormat;
+use dialect::{Dialect, Setting};
+use errors::{
+    DimensionMismatch, InvalidCountError, InvalidPatternError, WrongNumberOfArguments, WrongTypeOfArguments,
+};
+use span::Snippet;
+use typecheck::{Class, ExternObject, ObjectKind};
+use validate::Value;
+
+/// An intrinsic implemented in Rust and linked into the crate. The intrinsics
+/// of the standard library are built-ins; native libraries are only needed for
+/// intrinsics that aren't.
+pub trait Intrinsic: Sync {
+    fn name(&self) -> &'static str;
+
+    /// Initializes an object that calls the intrinsic with the arguments.
+    fn init(&self, this: &mut ExternObject, args: &[&ObjectKind]) -> Result<(), Error>;
+
+    /// Initializes an object that refers to the intrinsic without calling it.
+    /// Most intrinsics are only usable once called.
+    fn init_bare(&self, _this: &mut ExternObject) {}
+
+    /// Builds the runtime value of an object that `init` has checked. `args`
+    /// are the values of the arguments and `names` the names they're bound to
+    /// with `as`.
+    fn value<'a>(
+        &self,
+        this: &ExternObject,
+        args: Vec<Value<'a>>,
+        names: Vec<Option<&'a str>>,
+    ) -> Result<Value<'a>, Error>;
+
+    /// The runtime value of an object that refers to the intrinsic without
+    /// calling it.
+    fn bare_value<'a>(&self) -> Value<'a> {
+        Value::Intrinsic(self.name())
+    }
+}
+
+/// The built-in intrinsics, by name.
+pub struct Registry {
+    intrinsics: HashMap<&'static str, &'static Intrinsic>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Registry {
+            intrinsics: HashMap::new(),
+        }
+    }
+
+    /// A registry with the intrinsics of the standard library.
+    pub fn builtin() -> Self {
+        let mut registry = Registry::new();
+        registry.register(&Row);
+        registry.register(&Col);
+        registry.register(&Regexp);
+        registry.register(&INT);
+        registry.register(&UINT);
+        registry.register(&FLOAT);
+        registry.register(&DECIMAL);
+        registry.register(&Format);
+        registry.register(&Or);
+        registry.register(&And);
+        registry.register(&Many);
+        registry.register(&AtLeastOnce);
+        registry.register(&Optional);
+        registry.register(&Repeat);
+        registry.register(&DELIMITER);
+        registry.register(&QUOTE);
+        registry.register(&LINE_ENDING);
+        registry.register(&ENCODING);
+        registry.register(&HEADER);
+        registry.register(&WIDTHS);
+        registry.register(&DialectIntrinsic);
+        registry.register(&File);
+        registry.register(&Module);
+        registry.register(&Export);
+        registry
+    }
+
+    pub fn register(&mut self, intrinsic: &'static Intrinsic) {
+        self.intrinsics.insert(intrinsic.name(), intrinsic);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&'static Intrinsic> {
+        self.intrinsics.get(name).cloned()
+    }
+}
+
+fn dimensions(arg: &ObjectKind) -> (i16, i16) {
+    match arg {
+        ObjectKind::StrLit(_) => (1, 1),
+        ObjectKind::Extern(ex) => ex.dimensions,
+        _ => (0, 0),
+    }
+}
+
+/// String literals are cell types that match only themselves.
+fn is_cell(arg: &&ObjectKind) -> bool {
+    match arg {
+        ObjectKind::StrLit(_) => true,
+        ObjectKind::Extern(ex) => ex.class == Class::Cell,
+        _ => false,
+    }
+}
+
+fn is_layout(arg: &&ObjectKind) -> bool {
+    match arg {
+        ObjectKind::Extern(ex) => ex.class == Class::Layout,
+        _ => false,
+    }
+}
+
+/// The string literal of a single argument, which `init` has checked.
+fn str_arg<'a>(args: Vec<Value<'a>>) -> Result<Cow<'a, str>, Error> {
+    match args.into_iter().next() {
+        Some(Value::Str(string)) => Ok(string),
+        _ => Err(WrongTypeOfArguments(Snippet::default()).into()),
+    }
+}
+
+/// Describes the child of a `col` at `idx` by its position and name.
+fn describe_child(arg: &ObjectKind, idx: usize) -> String {
+    let kind = match arg {
+        ObjectKind::Extern(ex) if ex.dimensions.1 != 1 => "col",
+        _ => "row",
+    };
+    match arg {
+        ObjectKind::Extern(ExternObject { name: Some(ref name), .. }) => {
+            format!("the {} {} ({})", kind, idx + 1, name)
+        }
+        _ => format!("the {} {}", kind, idx + 1),
+    }
+}
+
+struct Row;
+
+impl Intrinsic for Row {
+    fn name(&self) -> &'static str {
+        "row"
+    }
+
+    fn init(&self, this: &mut ExternObject, args: &[&ObjectKind]) -> Result<(), Error> {
+        if !args.iter().all(is_cell) {
+            return Err(WrongTypeOfArguments(Snippet::default()).into());
+        }
+        this.class = Class::Layout;
+        this.dimensions = (args.len() as i16, 1);
+        Ok(())
+    }
+
+    fn value<'a>(
+        &self,
+        _this: &ExternObject,
+        args: Vec<Value<'a>>,
+        names: Vec<Option<&'a str>>,
+    ) -> Result<Value<'a>, Error> {
+        Ok(Value::Row(args, names))
+    }
+}
+
+struct Col;
+
+impl Intrinsic for Col {
+    fn name(&self) -> &'static str {
+        "col"
+    }
+
+    /// The children are rows and cols of equal widths. A single row describes
+    /// any number of lines, so a header row followed by a repeated body is
+    /// `col(row(...) col(row(...)))`.
+    fn init(&self, this: &mut ExternObject, args: &[&ObjectKind]) -> Result<(), Error> {
+        if !args.iter().all(is_layout) {
+            return Err(WrongTypeOfArguments(Snippet::default()).into());
+        }
+
+        let width = args.first().map(|arg| dimensions(arg).0).unwrap_or(0);
+        for (idx, arg) in args.iter().enumerate() {
+            if dimensions(arg).0 != width {
+                return Err(DimensionMismatch(
+                    describe_child(args[0], 0),
+                    width,
+                    describe_child(arg, idx),
+                    dimensions(arg).0,
+                    Snippet::default(),
+                ).into());
+            }
+        }
+
+        let height = args.iter().map(|arg| dimensions(arg).1).sum();
+        this.class = Class::Layout;
+        this.dimensions = (width, height);
+        Ok(())
+    }
+
+    fn value<'a>(
+        &self,
+        _this: &ExternObject,
+        args: Vec<Value<'a>>,
+        names: Vec<Option<&'a str>>,
+    ) -> Result<Value<'a>, Error> {
+        Ok(Value::Col(args, names))
+    }
+}
+
+struct Regexp;
+
+impl Intrinsic for Regexp {
+    fn name(&self) -> &'static str {
+        "regexp"
+    }
+
+    fn init(&self, this: &mut ExternObject, args: &[&ObjectKind]) -> Result<(), Error> {
+        if args.len() != 1 {
+            return Err(WrongNumberOfArguments(1, args.len(), Snippet::default()).into());
+        }
+        match args[0] {
+            ObjectKind::StrLit(pattern) => {
+                this.pattern = Some(compile_pattern(pattern)?);
+                this.class = Class::Cell;
+                this.dimensions = (1, 1);
+                Ok(())
+            }
+            _ => Err(WrongTypeOfArguments(Snippet::default()).into()),
+        }
+    }
+
+    fn value<'a>(
+        &self,
+        this: &ExternObject,
+        args: Vec<Value<'a>>,
+        _names: Vec<Option<&'a str>>,
+    ) -> Result<Value<'a>, Error> {
+        let regex = this.pattern.clone().expect("Invariant: init compiles the pattern.");
+        Ok(Value::Regexp(str_arg(args)?, regex))
+    }
+}
+
+/// The numeric cell types: `int(min max)`, `uint(min max)`, `float(precision)`
+/// and `decimal(scale)`. Without arguments, they're unconstrained.
+struct NumberIntrinsic(&'static str);
+
+const INT: NumberIntrinsic = NumberIntrinsic("int");
+const UINT: NumberIntrinsic = NumberIntrinsic("uint");
+const FLOAT: NumberIntrinsic = NumberIntrinsic("float");
+const DECIMAL: NumberIntrinsic = NumberIntrinsic("decimal");
+
+impl Intrinsic for NumberIntrinsic {
+    fn name(&self) -> &'static str {
+        self.0
+    }
+
+    fn init(&self, this: &mut ExternObject, args: &[&ObjectKind]) -> Result<(), Error> {
+        let args = args
+            .iter()
+            .map(|arg| match arg {
+                ObjectKind::Int(int) => Ok(*int),
+                _ => Err(WrongTypeOfArguments(Snippet::default())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        CellType::constrained(self.0, &args)?;
+        self.init_bare(this);
+        Ok(())
+    }
+
+    fn init_bare(&self, this: &mut ExternObject) {
+        this.class = Class::Cell;
+        this.dimensions = (1, 1);
+    }
+
+    fn value<'a>(
+        &self,
+        _this: &ExternObject,
+        args: Vec<Value<'a>>,
+        _names: Vec<Option<&'a str>>,
+    ) -> Result<Value<'a>, Error> {
+        let args = args
+            .iter()
+            .map(|arg| match arg {
+                Value::Int(int) => Ok(*int),
+                _ => Err(WrongTypeOfArguments(Snippet::default())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Value::Number(CellType::constrained(self.0, &args)?))
+    }
+
+    fn bare_value<'a>(&self) -> Value<'a> {
+        let cell_type = CellType::parse(self.0);
+        Value::Number(cell_type.expect("Invariant: the numeric intrinsics are cell types."))
+    }
+}
+
+/// `format(pattern)`: dates and times written in a strftime-style format,
+/// like `format("%Y-%m-%d")`.
+struct Format;
+
+impl Intrinsic for Format {
+    fn name(&self) -> &'static str {
+        "format"
+    }
+
+    fn init(&self, this: &mut ExternObject, args: &[&ObjectKind]) -> Result<(), Error> {
+        if args.len() != 1 {
+            return Err(WrongNumberOfArguments(1, args.len(), Snippet::default()).into());
+        }
+        match args[0] {
+            ObjectKind::StrLit(pattern) => DateFormat::parse(pattern)?,
+            _ => return Err(WrongTypeOfArguments(Snippet::default()).into()),
+        };
+        this.class = Class::Cell;
+        this.dimensions = (1, 1);
+        Ok(())
+    }
+
+    fn value<'a>(
+        &self,
+        _this: &ExternObject,
+        args: Vec<Value<'a>>,
+        _names: Vec<Option<&'a str>>,
+    ) -> Result<Value<'a>, Error> {
+        let pattern = str_arg(args)?;
+        let format = DateFormat::parse(&pattern)?;
+        Ok(Value::Format(pattern, format))
+    }
+}
+
+/// Compiles the pattern of `regexp`. The pattern is anchored so that it has to
+/// match the whole cell, and `.` matches the line breaks of quoted cells.
+pub fn compile_pattern(pattern: &str) -> Result<Regex, Error> {
+    // Checked before anchoring so that the error shows the pattern as written
+    if let Err(err) = Regex::new(pattern) {
+        let message = err.to_string();
+        return Err(InvalidPatternError(pattern.to_owned(), message, Snippet::default()).into());
+    }
+    Ok(Regex::new(&format!("(?s)^(?:{})$", pattern))?)
+}
+
+/// `or` and `and` combine cell types and string literals into a cell type.
+/// Layouts can't be combined.
+fn init_combinator(this: &mut ExternObject, args: &[&ObjectKind]) -> Result<(), Error> {
+    if !args.iter().all(is_cell) {
+        return Err(WrongTypeOfArguments(Snippet::default()).into());
+    }
+    this.class = Class::Cell;
+    this.dimensions = (1, 1);
+    Ok(())
+}
+
+struct Or;
+
+impl Intrinsic for Or {
+    fn name(&self) -> &'static str {
+        "or"
+    }
+
+    fn init(&self, this: &mut ExternObject, args: &[&ObjectKind]) -> Result<(), Error> {
+        init_combinator(this, args)
+    }
+
+    fn value<'a>(
+        &self,
+        _this: &ExternObject,
+        args: Vec<Value<'a>>,
+        _names: Vec<Option<&'a str>>,
+    ) -> Result<Value<'a>, Error> {
+        Ok(Value::Or(args))
+    }
+}
+
+struct And;
+
+impl Intrinsic for And {
+    fn name(&self) -> &'static str {
+        "and"
+    }
+
+    fn init(&self, this: &mut ExternObject, args: &[&ObjectKind]) -> Result<(), Error> {
+        init_combinator(this, args)
+    }
+
+    fn value<'a>(
+        &self,
+        _this: &ExternObject,
+        args: Vec<Value<'a>>,
+        _names: Vec<Option<&'a str>>,
+    ) -> Result<Value<'a>, Error> {
+        Ok(Value::And(args))
+    }
+}
+
+/// How many times a layout is repeated: at least `min` and at most `max`
+/// times, if there's a maximum.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Repetition {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl Repetition {
+    pub const MANY: Repetition = Repetition { min: 0, max: None };
+    pub const SOME: Repetition = Repetition { min: 1, max: None };
+    pub const OPTIONAL: Repetition = Repetition {
+        min: 0,
+        max: Some(1),
+    };
+
+    /// Parses the count of `repeat`: either a number like `3` or a range like
+    /// `1..3`, `2..` or `..5`. The ranges are inclusive.
+    pub fn parse(count: &str) -> Result<Repetition, Error> {
+        let invalid = || InvalidCountError(count.to_owned(), Snippet::default());
+        let bound = |bound: &str| bound.trim().parse::<usize>().map_err(|_| invalid());
+
+        let repetition = match count.find("..") {
+            Some(idx) => {
+                let (min, max) = (&count[..idx], &count[idx + 2..]);
+                Repetition {
+                    min: if min.trim().is_empty() { 0 } else { bound(min)? },
+                    max: if max.trim().is_empty() {
+                        None
+                    } else {
+                        Some(bound(max)?)
+                    },
+                }
+            }
+            None => {
+                let n = bound(count)?;
+                Repetition {
+                    min: n,
+                    max: Some(n),
+                }
+            }
+        };
+
+        match repetition.max {
+            Some(max) if max < repetition.min || max == 0 => Err(invalid().into()),
+            _ => Ok(repetition),
+        }
+    }
+
+    /// A repetition of exactly `count` times, given as an integer literal.
+    pub fn exactly(count: i64) -> Result<Repetition, Error> {
+        if count < 1 {
+            return Err(InvalidCountError(count.to_string(), Snippet::default()).into());
+        }
+        Ok(Repetition {
+            min: count as usize,
+            max: Some(count as usize),
+        })
+    }
+
+    /// Whether another repetition is allowed after `count` ones.
+    pub fn allows(&self, count: usize) -> bool {
+        self.max.map_or(true, |max| count < max)
+    }
+
+    /// The name of the intrinsic that produces the repetition.
+    pub fn name(&self) -> &'static str {
+        match (self.min, self.max) {
+            (0, None) => "many",
+            (1, None) => "some",
+            (0, Some(1)) => "optional",
+            _ => "repeat",
+        }
+    }
+}
+
+/// Formats the repetition as the count of `repeat`.
+impl fmt::Display for Repetition {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(formatter, "{}", max),
+            Some(max) => write!(formatter, "{}..{}", self.min, max),
+            None => write!(formatter, "{}..", self.min),
+        }
+    }
+}
+
+/// Checks the single layout argument of a repetition.
+fn init_repetition(this: &mut ExternObject, args: &[&ObjectKind]) -> Result<(), Error> {
+    if args.len() != 1 {
+        return Err(WrongNumberOfArguments(1, args.len(), Snippet::default()).into());
+    }
+    if !is_layout(&args[0]) {
+        return Err(WrongTypeOfArguments(Snippet::default()).into());
+    }
+    this.class = Class::Layout;
+    this.dimensions = dimensions(args[0]);
+    Ok(())
+}
+
+/// The value of a repetition of the single layout argument.
+fn repetition_value<'a>(repetition: Repetition, args: Vec<Value<'a>>) -> Result<Value<'a>, Error> {
+    match args.into_iter().next() {
+        Some(layout) => Ok(Value::Repeat(repetition, Box::new(layout))),
+        None => Err(WrongTypeOfArguments(Snippet::default()).into()),
+    }
+}
+
+/// `many(layout)`: any number of repetitions of the layout.
+struct Many;
+
+impl Intrinsic for Many {
+    fn name(&self) -> &'static str {
+        "many"
+    }
+
+    fn init(&self, this: &mut ExternObject, args: &[&ObjectKind]) -> Result<(), Error> {
+        init_repetition(this, args)
+    }
+
+    fn value<'a>(
+        &self,
+        _this: &ExternObject,
+        args: Vec<Value<'a>>,
+        _names: Vec<Option<&'a str>>,
+    ) -> Result<Value<'a>, Error> {
+        repetition_value(Repetition::MANY, args)
+    }
+}
+
+/// `some(layout)`: at least one repetition of the layout.
+struct AtLeastOnce;
+
+impl Intrinsic for AtLeastOnce {
+    fn name(&self) -> &'static str {
+        "some"
+    }
+
+    fn init(&self, this: &mut ExternObject, args: &[&ObjectKind]) -> Result<(), Error> {
+        init_repetition(this, args)
+    }
+
+    fn value<'a>(
+        &self,
+        _this: &ExternObject,
+        args: Vec<Value<'a>>,
+        _names: Vec<Option<&'a str>>,
+    ) -> Result<Value<'a>, Error> {
+        repetition_value(Repetition::SOME, args)
+    }
+}
+
+/// `optional(layout)`: the layout or nothing.
+struct Optional;
+
+impl Intrinsic for Optional {
+    fn name(&self) -> &'static str {
+        "optional"
+    }
+
+    fn init(&self, this: &mut ExternObject, args: &[&ObjectKind]) -> Result<(), Error> {
+        init_repetition(this, args)
+    }
+
+    fn value<'a>(
+        &self,
+        _this: &ExternObject,
+        args: Vec<Value<'a>>,
+        _names: Vec<Option<&'a str>>,
+    ) -> Result<Value<'a>, Error> {
+        repetition_value(Repetition::OPTIONAL, args)
+    }
+}
+
+/// `repeat(count layout)`: the layout repeated as many times as the count
+/// allows.
+struct Repeat;
+
+impl Intrinsic for Repeat {
+    fn name(&self) -> &'static str {
+        "repeat"
+    }
+
+    fn init(&self, this: &mut ExternObject, args: &[&ObjectKind]) -> Result<(), Error> {
+        if args.len() != 2 {
+            return Err(WrongNumberOfArguments(2, args.len(), Snippet::default()).into());
+        }
+        match args[0] {
+            ObjectKind::StrLit(count) => Repetition::parse(count)?,
+            ObjectKind::Int(count) => Repetition::exactly(*count)?,
+            _ => return Err(WrongTypeOfArguments(Snippet::default()).into()),
+        };
+        init_repetition(this, &args[1..])
+    }
+
+    fn value<'a>(
+        &self,
+        _this: &ExternObject,
+        args: Vec<Value<'a>>,
+        _names: Vec<Option<&'a str>>,
+    ) -> Result<Value<'a>, Error> {
+        let repetition = match args.first() {
+            Some(Value::Str(count)) => Repetition::parse(count)?,
+            Some(Value::Int(count)) => Repetition::exactly(*count)?,
+            _ => return Err(WrongTypeOfArguments(Snippet::default()).into()),
+        };
+        repetition_value(repetition, args.into_iter().skip(1).collect())
+    }
+}
+
+/// The settings of a dialect, like `delimiter(",")`.
+struct SettingIntrinsic(&'static str);
+
+const DELIMITER: SettingIntrinsic = SettingIntrinsic("delimiter");
+const QUOTE: SettingIntrinsic = SettingIntrinsic("quote");
+const LINE_ENDING: SettingIntrinsic = SettingIntrinsic("line_ending");
+const ENCODING: SettingIntrinsic = SettingIntrinsic("encoding");
+const HEADER: SettingIntrinsic = SettingIntrinsic("header");
+const WIDTHS: SettingIntrinsic = SettingIntrinsic("widths");
+
+impl Intrinsic for SettingIntrinsic {
+    fn name(&self) -> &'static str {
+        self.0
+    }
+
+    fn init(&self, this: &mut ExternObject, args: &[&ObjectKind]) -> Result<(), Error> {
+        if args.len() != 1 {
+            return Err(WrongNumberOfArguments(1, args.len(), Snippet::default()).into());
+        }
+        match args[0] {
+            ObjectKind::StrLit(value) => Setting::parse(self.0, value)?,
+            _ => return Err(WrongTypeOfArguments(Snippet::default()).into()),
+        };
+        this.class = Class::Setting;
+        Ok(())
+    }
+
+    fn value<'a>(
+        &self,
+        _this: &ExternObject,
+        args: Vec<Value<'a>>,
+        _names: Vec<Option<&'a str>>,
+    ) -> Result<Value<'a>, Error> {
+        let value = str_arg(args)?;
+        Ok(Value::Setting(Setting::parse(self.0, &value)?))
+    }
+}
+
+/// `dialect(settings...)`: how the data files are written.
+struct DialectIntrinsic;
+
+impl Intrinsic for DialectIntrinsic {
+    fn name(&self) -> &'static str {
+        "dialect"
+    }
+
+    fn init(&self, this: &mut ExternObject, args: &[&ObjectKind]) -> Result<(), Error> {
+        let is_setting = |arg: &&ObjectKind| match arg {
+            ObjectKind::Extern(ex) => ex.class == Class::Setting,
+            _ => false,
+        };
+        if !args.iter().all(is_setting) {
+            return Err(WrongTypeOfArguments(Snippet::default()).into());
+        }
+        this.class = Class::Dialect;
+        Ok(())
+    }
+
+    fn value<'a>(
+        &self,
+        _this: &ExternObject,
+        args: Vec<Value<'a>>,
+        _names: Vec<Option<&'a str>>,
+    ) -> Result<Value<'a>, Error> {
+        let settings = args
+            .iter()
+            .map(|arg| match arg {
+                Value::Setting(setting) => Ok(setting.clone()),
+                _ => Err(WrongTypeOfArguments(Snippet::default())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Value::Dialect(Dialect::with_settings(&settings)))
+    }
+}
+
+/// `file(dialect layout)`: a layout of data files written in the dialect.
+/// Files can't be nested in other layouts.
+struct File;
+
+impl Intrinsic for File {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn init(&self, this: &mut ExternObject, args: &[&ObjectKind]) -> Result<(), Error> {
+        if args.len() != 2 {
+            return Err(WrongNumberOfArguments(2, args.len(), Snippet::default()).into());
+        }
+        match args[0] {
+            ObjectKind::Extern(ex) if ex.class == Class::Dialect => (),
+            _ => return Err(WrongTypeOfArguments(Snippet::default()).into()),
+        }
+        if !is_layout(&args[1]) {
+            return Err(WrongTypeOfArguments(Snippet::default()).into());
+        }
+        this.class = Class::File;
+        this.dimensions = dimensions(args[1]);
+        Ok(())
+    }
+
+    fn value<'a>(
+        &self,
+        _this: &ExternObject,
+        args: Vec<Value<'a>>,
+        _names: Vec<Option<&'a str>>,
+    ) -> Result<Value<'a>, Error> {
+        let mut args = args.into_iter();
+        match (args.next(), args.next()) {
+            (Some(Value::Dialect(dialect)), Some(layout)) => {
+                Ok(Value::File(dialect, Box::new(layout)))
+            }
+            _ => Err(WrongTypeOfArguments(Snippet::default()).into()),
+        }
+    }
+}
+
+struct Module;
+
+impl Intrinsic for Module {
+    fn name(&self) -> &'static str {
+        "module"
+    }
+
+    fn init(&self, this: &mut ExternObject, _args: &[&ObjectKind]) -> Result<(), Error> {
+        this.dimensions = (0, 0);
+        Ok(())
+    }
+
+    fn value<'a>(
+        &self,
+        _this: &ExternObject,
+        _args: Vec<Value<'a>>,
+        _names: Vec<Option<&'a str>>,
+    ) -> Result<Value<'a>, Error> {
+        Ok(Value::Module)
+    }
+}
+
+struct Export;
+
+impl Intrinsic for Export {
+    fn name(&self) -> &'static str {
+        "export"
+    }
+
+    fn init(&self, this: &mut ExternObject, _args: &[&ObjectKind]) -> Result<(), Error> {
+        this.dimensions = (0, 0);
+        Ok(())
+    }
+
+    fn value<'a>(
+        &self,
+        _this: &ExternObject,
+        _args: Vec<Value<'a>>,
+        _names: Vec<Option<&'a str>>,
+    ) -> Result<Value<'a>, Error> {
+        Ok(Value::Export)
+    }
+}
+
+#[test]
+fn test_builtin_registry() {
+    let registry = Registry::builtin();
+
+    let mut row = ExternObject::builtin(registry.get("row").unwrap());
+    let cell = ObjectKind::StrLit("cell");
+    row.call(&[&cell, &cell]).unwrap();
+    assert_eq!(row.dimensions, (2, 1));
+
+    let mut regexp = ExternObject::builtin(registry.get("regexp").unwrap());
+    assert!(regexp.call(&[&cell, &cell]).is_err());
+
+    let pattern = ObjectKind::StrLit("[0-9");
+    assert!(regexp.call(&[&pattern]).is_err());
+    regexp.call(&[&ObjectKind::StrLit("[a-z]+")]).unwrap();
+    assert!(regexp.pattern.as_ref().unwrap().is_match("abc"));
+
+    let mut or = ExternObject::builtin(registry.get("or").unwrap());
+    or.call(&[&cell, &ObjectKind::Extern(regexp.clone())]).unwrap();
+    assert_eq!(or.class, Class::Cell);
+    assert!(or.call(&[&cell, &ObjectKind::Extern(row)]).is_err());
+
+    assert!(registry.get("nonexistent").is_none());
+}
+
+#[test]
+fn test_compile_pattern() {
+    let regex = compile_pattern("[0-9]+|x").unwrap();
+
+    assert!(regex.is_match("123"));
+    assert!(regex.is_match("x"));
+    assert!(!regex.is_match("123x"));
+    assert!(!regex.is_match(""));
+    assert!(compile_pattern(".*").unwrap().is_match("two\r\nlines"));
+}
+
+#[test]
+fn test_col_dimensions() {
+    let registry = Registry::builtin();
+    let cell = ObjectKind::StrLit("cell");
+
+    let mut header = ExternObject::builtin(registry.get("row").unwrap());
+    header.call(&[&cell, &cell]).unwrap();
+    let mut body = header.clone();
+    body.name = Some("body".to_owned());
+    body.call(&[&cell]).unwrap();
+
+    let mut col = ExternObject::builtin(registry.get("col").unwrap());
+    let header = ObjectKind::Extern(header);
+    col.call(&[&header, &header]).unwrap();
+    assert_eq!(col.dimensions, (2, 2));
+
+    let err = col.call(&[&header, &ObjectKind::Extern(body)]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Type error: all the rows of a col must have the same width, \
+         but the row 1 is 2 cells wide and the row 2 (body) is 1 cells wide."
+    );
+    assert!(col.call(&[&cell]).is_err());
+}
+
+#[test]
+fn test_repetition() {
+    let repetition = |min, max| Repetition { min, max };
+
+    assert_eq!(Repetition::parse("3").unwrap(), repetition(3, Some(3)));
+    assert_eq!(Repetition::parse("1..3").unwrap(), repetition(1, Some(3)));
+    assert_eq!(Repetition::parse("2..").unwrap(), repetition(2, None));
+    assert_eq!(Repetition::parse("..5").unwrap(), repetition(0, Some(5)));
+    assert!(Repetition::parse("3..1").is_err());
+    assert!(Repetition::parse("0").is_err());
+    assert!(Repetition::parse("many").is_err());
+    assert_eq!(Repetition::exactly(3).unwrap(), repetition(3, Some(3)));
+    assert!(Repetition::exactly(0).is_err());
+
+    assert_eq!(Repetition::OPTIONAL.name(), "optional");
+    assert_eq!(repetition(1, Some(3)).name(), "repeat");
+    assert_eq!(repetition(1, Some(3)).to_string(), "1..3");
+    assert_eq!(repetition(2, None).to_string(), "2..");
+}
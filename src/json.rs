@@ -0,0 +1,267 @@
+use std::io::{self, Write};
+
+use failure::Error;
+
+use data::Data;
+use validate::{CellReport, LineReport, Visitor};
+
+/// Writes typed data as a JSON document. Records become objects keyed by the
+/// `as` names of their fields, and the cells of rows that follow a header row
+/// of string literals are keyed by the header. The header applies to the
+/// layout right after it, and to everything nested in that layout. Records without any names
+/// become arrays, and unnamed fields among named ones are keyed by their
+/// position.
+pub fn write_json<W: Write>(data: &Data, writer: &mut W) -> io::Result<()> {
+    write_data(data, None, writer)?;
+    writer.write_all(b"\n")
+}
+
+/// Writes typed data as newline-delimited JSON: one line per row of the data,
+/// keyed like in `write_json`. Header rows are left out.
+pub fn write_ndjson<W: Write>(data: &Data, writer: &mut W) -> io::Result<()> {
+    for_each_row(data, None, &mut |row, columns| {
+        write_data(row, columns, writer)?;
+        writer.write_all(b"\n")
+    })
+}
+
+/// Writes the records of `parse_records` as newline-delimited JSON, like
+/// `write_ndjson` but without building the whole data first.
+pub struct NdjsonWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> NdjsonWriter<W> {
+    pub fn new(writer: W) -> Self {
+        NdjsonWriter { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> Visitor for NdjsonWriter<W> {
+    fn visit_row(&mut self, _cells: Vec<CellReport>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn visit_line(&mut self, _line: LineReport) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn visit_record(&mut self, record: Data, columns: Option<&[&str]>) -> Result<(), Error> {
+        write_data(&record, columns, &mut self.writer)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+fn is_scalar(data: &Data) -> bool {
+    match data {
+        Data::Record(_) | Data::List(_) => false,
+        _ => true,
+    }
+}
+
+/// The cells of a header row: an unnamed row of string literals.
+fn header<'d>(name: &Option<String>, data: &'d Data) -> Option<Vec<&'d str>> {
+    match (name, data) {
+        (None, Data::Record(cells)) if !cells.is_empty() => cells
+            .iter()
+            .map(|(_, cell)| match cell {
+                Data::Tag(tag) => Some(&**tag),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+fn is_row(fields: &[(Option<String>, Data)]) -> bool {
+    fields.iter().all(|(_, data)| is_scalar(data))
+}
+
+fn for_each_row<'d, F>(data: &'d Data, columns: Option<&[&str]>, f: &mut F) -> io::Result<()>
+where
+    F: FnMut(&'d Data, Option<&[&str]>) -> io::Result<()>,
+{
+    match data {
+        Data::Record(fields) if is_row(fields) => f(data, columns),
+        Data::Record(fields) => {
+            let mut header_columns = None;
+            for (name, field) in fields {
+                match header(name, field) {
+                    Some(header) => header_columns = Some(header),
+                    None => {
+                        let field_columns = header_columns.take();
+                        let field_columns = field_columns.as_ref().map(Vec::as_slice).or(columns);
+                        for_each_row(field, field_columns, f)?
+                    }
+                }
+            }
+            Ok(())
+        }
+        Data::List(items) => {
+            for item in items {
+                for_each_row(item, columns, f)?;
+            }
+            Ok(())
+        }
+        _ => f(data, columns),
+    }
+}
+
+fn write_data<W: Write>(data: &Data, columns: Option<&[&str]>, writer: &mut W) -> io::Result<()> {
+    match data {
+        Data::Str(string) | Data::Tag(string) => write_str(string, writer),
+        Data::Int(int) => write!(writer, "{}", int),
+        Data::UInt(uint) => write!(writer, "{}", uint),
+        Data::Float(float) if float.is_finite() => write!(writer, "{}", float),
+        Data::Float(_) => writer.write_all(b"null"),
+        Data::Decimal(decimal) => write!(writer, "{}", decimal),
+        Data::Date(date) => write_str(&date.to_string(), writer),
+        Data::Time(time) => write_str(&time.to_string(), writer),
+        Data::DateTime(datetime) => write_str(&datetime.to_string(), writer),
+        Data::List(items) => {
+            writer.write_all(b"[")?;
+            for (idx, item) in items.iter().enumerate() {
+                if idx > 0 {
+                    writer.write_all(b",")?;
+                }
+                write_data(item, columns, writer)?;
+            }
+            writer.write_all(b"]")
+        }
+        Data::Record(fields) if is_row(fields) => {
+            let keys = fields
+                .iter()
+                .enumerate()
+                .map(|(idx, (name, _))| {
+                    name.clone()
+                        .or_else(|| columns.and_then(|columns| columns.get(idx)).map(|c| c.to_string()))
+                })
+                .collect::<Vec<_>>();
+            let fields = fields.iter().map(|(_, data)| (data, None)).collect::<Vec<_>>();
+            write_fields(&keys, &fields, writer)
+        }
+        Data::Record(fields) => {
+            let mut header_columns = None;
+            let mut keys = Vec::new();
+            let mut values = Vec::new();
+            for (name, field) in fields {
+                match header(name, field) {
+                    Some(header) => header_columns = Some(header),
+                    None => {
+                        let field_columns = header_columns.take().or_else(|| columns.map(<[&str]>::to_vec));
+                        keys.push(name.clone());
+                        values.push((field, field_columns));
+                    }
+                }
+            }
+            write_fields(&keys, &values, writer)
+        }
+    }
+}
+
+/// Writes an object, or an array if none of the fields has a key.
+fn write_fields<W: Write>(
+    keys: &[Option<String>],
+    fields: &[(&Data, Option<Vec<&str>>)],
+    writer: &mut W,
+) -> io::Result<()> {
+    let is_object = keys.iter().any(Option::is_some);
+    writer.write_all(if is_object { b"{" } else { b"[" })?;
+    for (idx, (key, (field, columns))) in keys.iter().zip(fields).enumerate() {
+        if idx > 0 {
+            writer.write_all(b",")?;
+        }
+        if is_object {
+            match key {
+                Some(key) => write_str(key, writer)?,
+                None => write_str(&idx.to_string(), writer)?,
+            }
+            writer.write_all(b":")?;
+        }
+        write_data(field, columns.as_ref().map(Vec::as_slice), writer)?;
+    }
+    writer.write_all(if is_object { b"}" } else { b"]" })
+}
+
+fn write_str<W: Write>(string: &str, writer: &mut W) -> io::Result<()> {
+    writer.write_all(b"\"")?;
+    for c in string.chars() {
+        match c {
+            '"' => writer.write_all(b"\\\"")?,
+            '\\' => writer.write_all(b"\\\\")?,
+            '\n' => writer.write_all(b"\\n")?,
+            '\r' => writer.write_all(b"\\r")?,
+            '\t' => writer.write_all(b"\\t")?,
+            c if (c as u32) < 0x20 => write!(writer, "\\u{:04x}", c as u32)?,
+            c => write!(writer, "{}", c)?,
+        }
+    }
+    writer.write_all(b"\"")
+}
+
+#[cfg(test)]
+fn to_string(write: fn(&Data, &mut Vec<u8>) -> io::Result<()>, data: &Data) -> String {
+    let mut out = Vec::new();
+    write(data, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_write_json() {
+    use data::Date;
+
+    let tag = |tag: &str| (None, Data::Tag(tag.to_owned()));
+    let row = |name: &str, count| {
+        Data::Record(vec![
+            (None, Data::Str(name.to_owned())),
+            (None, Data::UInt(count)),
+        ])
+    };
+    let data = Data::Record(vec![
+        (None, Data::Record(vec![tag("name"), tag("count")])),
+        (
+            Some("data".to_owned()),
+            Data::List(vec![row("alice", 5), row("\"bob\"\n", 3)]),
+        ),
+        (
+            Some("footer".to_owned()),
+            Data::Record(vec![
+                (None, Data::Float(1.5)),
+                (
+                    Some("when".to_owned()),
+                    Data::Date(Date {
+                        year: 2018,
+                        month: 6,
+                        day: 20,
+                    }),
+                ),
+            ]),
+        ),
+    ]);
+
+    assert_eq!(
+        to_string(write_json, &data),
+        concat!(
+            r#"{"data":[{"name":"alice","count":5},{"name":"\"bob\"\n","count":3}],"#,
+            r#""footer":{"0":1.5,"when":"2018-06-20"}}"#,
+            "\n"
+        )
+    );
+    assert_eq!(
+        to_string(write_ndjson, &data),
+        concat!(
+            r#"{"name":"alice","count":5}"#,
+            "\n",
+            r#"{"name":"\"bob\"\n","count":3}"#,
+            "\n",
+            r#"{"0":1.5,"when":"2018-06-20"}"#,
+            "\n"
+        )
+    );
+    assert_eq!(to_string(write_json, &row("a", 1)), "[\"a\",1]\n");
+}
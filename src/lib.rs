@@ -8,6 +8,8 @@ extern crate failure;
 extern crate log;
 
 extern crate env_logger;
+#[cfg_attr(test, macro_use)]
+extern crate kadouchi_abi;
 extern crate libloading;
 extern crate regex;
 extern crate scoped_stack;
@@ -17,13 +19,9 @@ extern crate scoped_stack;
  REFACTOR
  - use a newtype to represent allowed symbols instead of arbitrary &str
 
- PARSING
- - support for escaping in string literals
-
  ERROR HANDLING
  - support clean error messages
  - review the error places and add error cases where needed
- - support spans etc. better reporting
 
  NAMERES
 
@@ -35,25 +33,54 @@ extern crate scoped_stack;
 
 */
 
+use std::collections::HashMap;
+use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::iter;
+use std::path::{Path, PathBuf};
 use std::str::from_utf8;
 
-use failure::Error;
+pub use failure::Error;
 
+mod codegen;
+mod data;
+mod datetime;
+mod dialect;
 mod errors;
+mod intrinsics;
+mod json;
+mod loader;
 mod nameres;
+mod span;
 mod tokens;
 mod typecheck;
+mod validate;
 
+pub use codegen::generate_rust;
+pub use data::{CellType, Data, Date, DateTime, Decimal, Fields, Time};
+pub use dialect::{Dialect, Encoding, LineEnding, Record, RecordReader};
+pub use errors::{exit_code, DataShapeError, InvalidDataError, TooManyMismatchesError};
 use errors::InvalidLibraryFileName;
+use intrinsics::Registry;
+use libloading::Library;
+pub use json::{write_json, write_ndjson, NdjsonWriter};
+use loader::{LoadedLibrary, Source};
+pub use loader::{SearchPath, ENV_PATH};
 pub use nameres::{AbsPath2, Item, Namespace};
+use span::SourceMap;
 use tokens::Exp;
+pub use validate::{
+    file_layout, parse_file, parse_reader, parse_records, validate_file, validate_reader, CellReport,
+    ErrorLimit, LineReport, Mismatch, Report, Value, Visitor,
+};
 
 const KEYWORD_AS: &str = "as";
 const KEYWORD_EXPORT: &str = "export";
 const KEYWORD_ROOT: &str = "root";
 const KEYWORD_INTRINSIC: &str = "intrinsic";
+const KEYWORD_IMPORT: &str = "import";
+const KEYWORD_USE: &str = "use";
+const KEYWORD_NO_PRELUDE: &str = "no_prelude";
 
 const LIBNAME_STD: &str = "std";
 const LIBNAME_PRELUDE: &str = "prelude";
@@ -64,20 +91,95 @@ pub fn parse_lib<'ns, 'str: 'ns>(
     root: &'ns mut Item<'str>,
     prelude_path: Option<&AbsPath2>,
 ) -> Result<AbsPath2, Error> {
-    let string = from_utf8(bytes)?;
+    let lib = LoadedLibrary::parse(libname, None, bytes)?;
+    resolve_lib(lib, root, prelude_path)
+}
 
-    info!("Parsing {}.", libname);
+/// Resolves the names of a library parsed into tokens and adds it to the
+/// tree. Besides `intrinsic`, the only libraries in its scope are the standard
+/// library, the prelude and the libraries it imports.
+fn resolve_lib<'ns, 'str: 'ns>(
+    lib: LoadedLibrary<'str>,
+    root: &'ns mut Item<'str>,
+    prelude_path: Option<&AbsPath2>,
+) -> Result<AbsPath2, Error> {
+    // The imports are loaded before, so they're only left out here
+    let (uses, token_tree): (Vec<Exp<'str>>, Vec<Exp<'str>>) = lib
+        .token_tree
+        .into_iter()
+        .filter(|exp| !loader::is_import(exp))
+        .partition(|exp| exp.use_decl().is_some());
+
+    let prelude_path = if token_tree.iter().any(is_no_prelude) {
+        None
+    } else {
+        prelude_path
+    };
+    let token_tree = token_tree
+        .into_iter()
+        .filter(|exp| !is_no_prelude(exp))
+        .collect::<Vec<_>>();
+
+    let mut libraries = vec![KEYWORD_INTRINSIC, LIBNAME_STD, LIBNAME_PRELUDE];
+    libraries.extend(&lib.imports);
+
+    info!("Doing name resolution for {}.", lib.name);
+
+    let mut item = nameres::resolve(lib.name, &uses, &token_tree, root, prelude_path, &libraries)?;
+    let idx = root.next_idx();
+    item.path = AbsPath2::new(vec![idx]);
+    root.add_child(item);
 
-    let token_tree: Vec<Exp<'str>> = tokens::parse_file(string)?;
+    Ok(AbsPath2::new(vec![idx]))
+}
 
-    info!("Doing name resolution for {}.", libname);
+/// A typechecked schema: the resolved tree of its libraries and the runtime
+/// values of their items.
+pub struct Schema<'a> {
+    pub root: Item<'a>,
+    values: HashMap<AbsPath2, Value<'a>>,
+    files: Vec<(String, Option<PathBuf>)>,
+}
 
-    let mut lib = nameres::resolve(libname, &token_tree, root, prelude_path)?;
-    let idx = root.next_idx();
-    lib.path = AbsPath2::new(vec![idx]);
-    root.add_child(lib);
+impl<'a> Schema<'a> {
+    /// Typechecks the tree. The intrinsics are either built-ins or provided by
+    /// the native companions in `natives`, keyed by the library name.
+    fn check(root: Item<'a>, natives: &HashMap<&str, Library>) -> Result<Schema<'a>, Error> {
+        let values = typecheck::check(&root, &Registry::builtin(), natives)?;
+        Ok(Schema {
+            root,
+            values,
+            files: Vec::new(),
+        })
+    }
+
+    /// The runtime value of an item of the tree. Only the items that are
+    /// libraries, literals or refer to something have one.
+    pub fn value(&self, item: &Item) -> Option<&Value<'a>> {
+        self.values.get(&item.path)
+    }
+
+    /// The libraries of the schema in dependency order, the standard library
+    /// and the prelude first and the schema last, with the files they were
+    /// read from. The bundled libraries and the sources parsed from memory
+    /// have no file.
+    pub fn files(&self) -> &[(String, Option<PathBuf>)] {
+        &self.files
+    }
+}
 
-    Ok(AbsPath2::new(vec![idx]))
+impl<'a> fmt::Debug for Schema<'a> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.root.fmt(formatter)
+    }
+}
+
+/// Whether an expression is a top-level `no_prelude`, which opts the library
+/// out of importing the prelude.
+fn is_no_prelude(exp: &Exp) -> bool {
+    exp.bound_name().is_none() && exp.call().map_or(false, |call| {
+        call.args.is_empty() && call.path.only_segment() == Some(KEYWORD_NO_PRELUDE)
+    })
 }
 
 fn get_libname(filename: &Path) -> Option<&str> {
@@ -92,32 +194,153 @@ fn test_get_libname() {
     assert_eq!(get_libname(Path::new("src/std.ku")), Some("std"))
 }
 
+/// Parses a schema with the standard library, looking up the libraries from
+/// `KADOUCHI_PATH`. The standard library is bundled.
 pub fn parse_with_stdlib<'a>(
     filename: &'a Path,
     bytestore: &'a mut Vec<Vec<u8>>,
-) -> Result<Item<'a>, Error> {
-    let mut root = Item::named(KEYWORD_ROOT);
+) -> Result<Schema<'a>, Error> {
+    parse_with_search_path(filename, &SearchPath::from_env(), bytestore)
+}
 
-    bytestore.push(fs::read("src/stdlib/std.ku")?);
-    bytestore.push(fs::read("src/stdlib/prelude.ku")?);
-    bytestore.push(fs::read(filename)?);
+/// Parses a schema with the standard library and the libraries it imports,
+/// looking up the libraries and their native companions from a search path.
+/// The imports and the native companions of the schema are looked up from its
+/// own directory first. `bytestore` keeps the sources that the tree borrows.
+pub fn parse_with_search_path<'a>(
+    filename: &'a Path,
+    search_path: &SearchPath,
+    bytestore: &'a mut Vec<Vec<u8>>,
+) -> Result<Schema<'a>, Error> {
+    let std = search_path.read_library(LIBNAME_STD)?;
+    let prelude = search_path.read_library(LIBNAME_PRELUDE)?;
+
+    let libname = get_libname(filename)
+        .ok_or_else(|| InvalidLibraryFileName(filename.to_string_lossy().to_string()))?;
+    let mut schema_path = search_path.clone();
+    schema_path.push_front(loader::schema_dir(filename));
+
+    let schema = Source {
+        name: libname.to_owned(),
+        path: filename.to_owned(),
+        bytes: fs::read(filename)?,
+    };
+    let libs = loader::load_imports(schema, &schema_path)?;
+
+    // Every source is stored along with its name before the tree borrows them
+    let base = bytestore.len();
+    let mut paths = Vec::new();
+    for source in iter::once(std).chain(iter::once(prelude)).chain(libs) {
+        paths.push((source.file().map(Path::to_owned), source.path));
+        bytestore.push(source.bytes);
+        bytestore.push(source.name.into_bytes());
+    }
+    let bytestore: &'a Vec<Vec<u8>> = bytestore;
+
+    let mut all = Vec::new();
+    for ((file, path), stored) in paths.into_iter().zip(bytestore[base..].chunks(2)) {
+        all.push((from_utf8(&stored[1])?, path, file, &stored[0][..]));
+    }
+    parse_libraries(all, search_path, &schema_path)
+}
+
+/// Parses a schema from sources in memory, like the ones embedded in the
+/// generated code. The standard library and the prelude default to the
+/// bundled ones, and the user libraries are given in dependency order, the
+/// schema last. The native companions are looked up from the search path.
+pub fn parse_sources<'a>(
+    std: Option<&'a str>,
+    prelude: Option<&'a str>,
+    libs: &[(&'a str, &'a str)],
+    search_path: &SearchPath,
+) -> Result<Schema<'a>, Error> {
+    let std = std
+        .map(str::as_bytes)
+        .or_else(|| loader::bundled(LIBNAME_STD))
+        .expect("Invariant: the standard library is bundled.");
+    let prelude = prelude
+        .map(str::as_bytes)
+        .or_else(|| loader::bundled(LIBNAME_PRELUDE))
+        .expect("Invariant: the prelude is bundled.");
+    let stdlib = [(LIBNAME_STD, std), (LIBNAME_PRELUDE, prelude)];
+    let all = stdlib
+        .iter()
+        .cloned()
+        .chain(libs.iter().map(|&(name, source)| (name, source.as_bytes())))
+        .map(|(name, bytes)| (name, PathBuf::from(format!("{}.ku", name)), None, bytes))
+        .collect();
+
+    parse_libraries(all, search_path, search_path)
+}
+
+/// Parses the sources of the standard library, the prelude and the user
+/// libraries, given as their names, paths, files and bytes in dependency
+/// order, and builds the tree from them.
+fn parse_libraries<'a>(
+    all: Vec<(&'a str, PathBuf, Option<PathBuf>, &'a [u8])>,
+    search_path: &SearchPath,
+    schema_path: &SearchPath,
+) -> Result<Schema<'a>, Error> {
+    let mut sources = SourceMap::new();
+    let mut loaded = Vec::new();
+    for (name, path, file, bytes) in all {
+        sources.add(&path.to_string_lossy(), bytes);
+        let lib = LoadedLibrary::parse(name, file, bytes);
+        loaded.push(lib.map_err(|err| errors::locate(err, &sources))?);
+    }
+
+    let mut loaded = loaded.into_iter();
+    let std = loaded.next().expect("Invariant: the standard library is loaded first.");
+    let prelude = loaded.next().expect("Invariant: the prelude is loaded second.");
+
+    build_root(std, prelude, loaded.collect(), search_path, schema_path)
+        .map_err(|err| errors::locate(err, &sources))
+}
+
+/// Builds the tree of the standard library, the prelude and the user
+/// libraries, which are given in dependency order.
+fn build_root<'a>(
+    std: LoadedLibrary<'a>,
+    prelude: LoadedLibrary<'a>,
+    libs: Vec<LoadedLibrary<'a>>,
+    search_path: &SearchPath,
+    schema_path: &SearchPath,
+) -> Result<Schema<'a>, Error> {
+    let files = iter::once(&std)
+        .chain(iter::once(&prelude))
+        .chain(&libs)
+        .map(|lib| (lib.name.to_owned(), lib.file.clone()))
+        .collect();
+    let names = libs.iter().map(|lib| lib.name).collect::<Vec<_>>();
+
+    let mut root = Item::named(KEYWORD_ROOT);
 
     let mut intrinsic = Item::named(KEYWORD_INTRINSIC);
     intrinsic.path = AbsPath2::new(vec![0]);
     root.add_child(intrinsic);
 
-    parse_lib(LIBNAME_STD, &bytestore[0], &mut root, None)?;
+    resolve_lib(std, &mut root, None)?;
 
-    let prelude_path = parse_lib(LIBNAME_PRELUDE, &bytestore[1], &mut root, None)?;
+    let prelude_path = resolve_lib(prelude, &mut root, None)?;
 
-    let libname = get_libname(filename)
-        .ok_or_else(|| InvalidLibraryFileName(filename.to_string_lossy().to_string()))?;
+    for lib in libs {
+        resolve_lib(lib, &mut root, Some(&prelude_path))?;
+    }
 
-    parse_lib(libname, &bytestore[2], &mut root, Some(&prelude_path))?;
+    let mut natives = HashMap::new();
+    for &name in &[LIBNAME_STD, LIBNAME_PRELUDE] {
+        if let Some(native) = search_path.load_native(name)? {
+            natives.insert(name, native);
+        }
+    }
+    for libname in names {
+        if let Some(native) = schema_path.load_native(libname)? {
+            natives.insert(libname, native);
+        }
+    }
 
-    typecheck::check(&mut root)?;
-
-    Ok(root)
+    let schema = Schema::check(root, &natives)?;
+    Ok(Schema { files, ..schema })
 }
 
 #[test]
@@ -135,7 +358,7 @@ fn parse_typecheck_name_ref() {
 
     parse_lib(LIBNAME_STD, &bytestore[0], &mut root, None).unwrap();
 
-    typecheck::check(&mut root).unwrap();
+    typecheck::check(&root, &Registry::builtin(), &HashMap::new()).unwrap();
 
     println!("{:#?}", root);
 }
@@ -146,7 +369,18 @@ fn parse_simple_lib_with_std() {
 
     let mut bytestore = Vec::new();
 
-    let root = parse_with_stdlib(Path::new("tests/fixtures/simple.ku"), &mut bytestore).unwrap();
+    let schema = parse_with_stdlib(Path::new("tests/fixtures/simple.ku"), &mut bytestore).unwrap();
+
+    println!("parse_simple_lib_with_std finished {:#?}", schema);
+}
+
+#[test]
+fn parse_sources_with_bundled_stdlib() {
+    let source = fs::read_to_string("tests/fixtures/simple.ku").unwrap();
+
+    let schema = parse_sources(None, None, &[("simple", &source)], &SearchPath::new()).unwrap();
 
-    println!("parse_simple_lib_with_std finished {:#?}", root);
+    assert_eq!(schema.files().len(), 3);
+    assert_eq!(schema.files()[2], ("simple".to_owned(), None));
+    assert!(generate_rust(&schema).is_err());
 }
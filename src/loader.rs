@@ -0,0 +1,347 @@
+use std::env;
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::from_utf8;
+
+use failure::Error;
+use kadouchi_abi::{ABI_VERSION, ABI_VERSION_SYMBOL};
+use libloading::{Library, Symbol};
+
+use errors::{
+    self, AbiVersionError, ImportCycleError, InvalidImportError, LibraryNotFoundError,
+    UnknownLibraryError,
+};
+use span::{Snippet, SourceMap, Span};
+use tokens::{self, Exp};
+use {KEYWORD_IMPORT, LIBNAME_PRELUDE, LIBNAME_STD};
+
+/// The environment variable that lists extra library directories, separated
+/// like `PATH`.
+pub const ENV_PATH: &str = "KADOUCHI_PATH";
+
+/// The standard library and the prelude, bundled into the binary so that
+/// they're found wherever it's installed.
+const BUNDLED: &[(&str, &[u8])] = &[
+    (LIBNAME_STD, include_bytes!("stdlib/std.ku")),
+    (LIBNAME_PRELUDE, include_bytes!("stdlib/prelude.ku")),
+];
+
+/// The directory that the paths of the bundled libraries are reported in.
+const BUNDLED_DIR: &str = "<bundled>";
+
+/// The source of a bundled library.
+pub fn bundled(libname: &str) -> Option<&'static [u8]> {
+    BUNDLED
+        .iter()
+        .find(|&&(name, _)| name == libname)
+        .map(|&(_, bytes)| bytes)
+}
+
+/// The directories where `.ku` libraries and their native companions are
+/// looked up, in order of priority.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SearchPath {
+    dirs: Vec<PathBuf>,
+}
+
+impl SearchPath {
+    pub fn new() -> Self {
+        SearchPath { dirs: Vec::new() }
+    }
+
+    /// The directories listed in `KADOUCHI_PATH`.
+    pub fn from_env() -> Self {
+        let mut search_path = SearchPath::new();
+        if let Some(paths) = env::var_os(ENV_PATH) {
+            search_path.extend_from(&paths);
+        }
+        search_path
+    }
+
+    /// Adds directories separated like `PATH`.
+    pub fn extend_from(&mut self, paths: &OsStr) {
+        self.dirs
+            .extend(env::split_paths(paths).filter(|dir| !dir.as_os_str().is_empty()));
+    }
+
+    /// Adds a directory with the lowest priority.
+    pub fn push<P: Into<PathBuf>>(&mut self, dir: P) {
+        self.dirs.push(dir.into());
+    }
+
+    /// Adds a directory with the highest priority.
+    pub fn push_front<P: Into<PathBuf>>(&mut self, dir: P) {
+        self.dirs.insert(0, dir.into());
+    }
+
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    fn find(&self, file_name: &str) -> Option<PathBuf> {
+        self.dirs
+            .iter()
+            .map(|dir| dir.join(file_name))
+            .filter(|path| path.is_file())
+            .next()
+    }
+
+    /// Finds the source file of a library.
+    pub fn find_library(&self, libname: &str) -> Result<PathBuf, Error> {
+        self.find(&format!("{}.ku", libname))
+            .ok_or_else(|| LibraryNotFoundError(libname.to_owned()).into())
+    }
+
+    /// Reads the source of a library. The standard library and the prelude
+    /// fall back to the bundled ones if the search path doesn't have them.
+    pub fn read_library(&self, libname: &str) -> Result<Source, Error> {
+        if let Some(path) = self.find(&format!("{}.ku", libname)) {
+            let bytes = fs::read(&path)?;
+            return Ok(Source {
+                name: libname.to_owned(),
+                path,
+                bytes,
+            });
+        }
+        match bundled(libname) {
+            Some(bytes) => Ok(Source {
+                name: libname.to_owned(),
+                path: Path::new(BUNDLED_DIR).join(format!("{}.ku", libname)),
+                bytes: bytes.to_vec(),
+            }),
+            None => Err(LibraryNotFoundError(libname.to_owned()).into()),
+        }
+    }
+
+    /// Loads the native companion of a library, if it has one. The companion
+    /// of `std` is `libstd.so` on Linux, `libstd.dylib` on macOS and `std.dll`
+    /// on Windows.
+    pub fn load_native(&self, libname: &str) -> Result<Option<Library>, Error> {
+        match self.find(&native_file_name(libname)) {
+            Some(path) => {
+                info!("Loading the native library {}.", path.display());
+                let native = Library::new(&path)?;
+                check_abi_version(&path, &native)?;
+                Ok(Some(native))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// The source of a library read from a file.
+pub struct Source {
+    pub name: String,
+    pub path: PathBuf,
+    pub bytes: Vec<u8>,
+}
+
+impl Source {
+    /// The file that the source was read from, unless it's bundled.
+    pub fn file(&self) -> Option<&Path> {
+        if self.path.starts_with(BUNDLED_DIR) {
+            None
+        } else {
+            Some(&self.path)
+        }
+    }
+}
+
+/// A library parsed into tokens, with the names of the libraries it imports.
+pub struct LoadedLibrary<'a> {
+    pub name: &'a str,
+    /// The file of the source, unless it's bundled or given in memory
+    pub file: Option<PathBuf>,
+    pub token_tree: Vec<Exp<'a>>,
+    pub imports: Vec<&'a str>,
+}
+
+impl<'a> LoadedLibrary<'a> {
+    pub fn parse(name: &'a str, file: Option<PathBuf>, bytes: &'a [u8]) -> Result<Self, Error> {
+        info!("Parsing {}.", name);
+        let token_tree = tokens::parse_file(from_utf8(bytes)?)?;
+        let imports = imports(&token_tree)?;
+        Ok(LoadedLibrary {
+            name,
+            file,
+            token_tree,
+            imports,
+        })
+    }
+}
+
+/// Whether an expression is an `import(...)` of other libraries.
+pub fn is_import(exp: &Exp) -> bool {
+    exp.call()
+        .map_or(false, |call| call.path.only_segment() == Some(KEYWORD_IMPORT))
+}
+
+/// The names of the libraries imported by the top-level `import(...)`
+/// expressions, like `import(shapes units)`.
+pub fn imports<'a>(token_tree: &[Exp<'a>]) -> Result<Vec<&'a str>, InvalidImportError> {
+    let mut names = Vec::new();
+    for exp in token_tree.iter().filter(|exp| is_import(exp)) {
+        if exp.bound_name().is_some() {
+            return Err(InvalidImportError(Snippet::new(exp.span())));
+        }
+        for arg in exp.call_args() {
+            let name = arg
+                .call()
+                .filter(|call| call.args.is_empty() && arg.bound_name().is_none())
+                .and_then(|call| call.path.only_segment());
+            match name {
+                Some(name) => names.push(name),
+                None => return Err(InvalidImportError(Snippet::new(arg.span()))),
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Reads a schema and the libraries it imports, transitively, from the search
+/// path. The sources are returned in dependency order, the schema last. The
+/// standard library and the prelude are always loaded, so importing them is a
+/// no-op.
+pub fn load_imports(schema: Source, search_path: &SearchPath) -> Result<Vec<Source>, Error> {
+    let mut loaded = Vec::new();
+    load_recursive(schema, search_path, &mut Vec::new(), &mut loaded)?;
+    Ok(loaded)
+}
+
+fn load_recursive(
+    source: Source,
+    search_path: &SearchPath,
+    loading: &mut Vec<String>,
+    loaded: &mut Vec<Source>,
+) -> Result<(), Error> {
+    // The errors are located while the source they point to is in memory
+    let mut sources = SourceMap::new();
+    sources.add(&source.path.to_string_lossy(), &source.bytes);
+
+    let result = load_imports_of(&source, search_path, loading, loaded);
+    result.map_err(|err| errors::locate(err, &sources))?;
+
+    loaded.push(source);
+    Ok(())
+}
+
+fn load_imports_of(
+    source: &Source,
+    search_path: &SearchPath,
+    loading: &mut Vec<String>,
+    loaded: &mut Vec<Source>,
+) -> Result<(), Error> {
+    let token_tree = tokens::parse_file(from_utf8(&source.bytes)?)?;
+
+    loading.push(source.name.clone());
+    for name in imports(&token_tree)? {
+        let is_loaded = loaded.iter().any(|lib| lib.name == name);
+        if is_loaded || name == LIBNAME_STD || name == LIBNAME_PRELUDE {
+            continue;
+        }
+        if let Some(start) = loading.iter().position(|lib| lib == name) {
+            let mut cycle = loading[start..].to_vec();
+            cycle.push(name.to_owned());
+            let span = Snippet::new(Span::of(name));
+            return Err(ImportCycleError(cycle.join(" -> "), span).into());
+        }
+
+        let path = search_path
+            .find_library(name)
+            .map_err(|_| UnknownLibraryError(name.to_owned(), Snippet::new(Span::of(name))))?;
+        info!("Loading the library {} from {}.", name, path.display());
+        let bytes = fs::read(&path)?;
+        let import = Source {
+            name: name.to_owned(),
+            path,
+            bytes,
+        };
+        load_recursive(import, search_path, loading, loaded)?;
+    }
+    loading.pop();
+    Ok(())
+}
+
+fn check_abi_version(path: &Path, native: &Library) -> Result<(), Error> {
+    let version = unsafe {
+        native
+            .get::<*const u32>(ABI_VERSION_SYMBOL)
+            .ok()
+            .map(|symbol: Symbol<*const u32>| **symbol)
+    };
+
+    if version == Some(ABI_VERSION) {
+        Ok(())
+    } else {
+        Err(AbiVersionError(path.display().to_string(), version, ABI_VERSION).into())
+    }
+}
+
+fn native_file_name(libname: &str) -> String {
+    format!("{}{}{}", DLL_PREFIX, libname, DLL_SUFFIX)
+}
+
+/// The directory of a schema file, for looking up its native companion.
+pub fn schema_dir(filename: &Path) -> PathBuf {
+    match filename.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+        _ => PathBuf::from("."),
+    }
+}
+
+#[test]
+fn test_find_library() {
+    let mut search_path = SearchPath::new();
+    search_path.push("tests");
+    search_path.push("tests/fixtures");
+
+    assert_eq!(
+        search_path.find_library("simple").unwrap(),
+        Path::new("tests/fixtures/simple.ku")
+    );
+    assert!(search_path.find_library("nonexistent").is_err());
+    assert!(search_path.load_native("simple").unwrap().is_none());
+}
+
+#[test]
+fn test_read_bundled_library() {
+    let search_path = SearchPath::new();
+
+    let std = search_path.read_library(LIBNAME_STD).unwrap();
+    assert_eq!(std.path, Path::new("<bundled>/std.ku"));
+    assert_eq!(std.bytes, &include_bytes!("stdlib/std.ku")[..]);
+    assert_eq!(std.file(), None);
+
+    let mut search_path = SearchPath::new();
+    search_path.push("tests/fixtures");
+    let simple = search_path.read_library("simple").unwrap();
+    assert_eq!(simple.path, Path::new("tests/fixtures/simple.ku"));
+    assert_eq!(simple.file(), Some(Path::new("tests/fixtures/simple.ku")));
+    assert!(search_path.read_library("nonexistent").is_err());
+}
+
+#[test]
+fn test_extend_from() {
+    let mut search_path = SearchPath::new();
+    search_path.push("first");
+    search_path.extend_from(&env::join_paths(&["second", "", "third"]).unwrap());
+    search_path.push_front("zeroth");
+
+    assert_eq!(
+        search_path.dirs(),
+        &[
+            PathBuf::from("zeroth"),
+            PathBuf::from("first"),
+            PathBuf::from("second"),
+            PathBuf::from("third"),
+        ][..]
+    );
+}
+
+#[test]
+fn test_schema_dir() {
+    assert_eq!(schema_dir(Path::new("schema.ku")), Path::new("."));
+    assert_eq!(schema_dir(Path::new("a/b/schema.ku")), Path::new("a/b"));
+}
@@ -1,3 +1,291 @@
+extern crate env_logger;
+#[macro_use]
+extern crate failure;
+extern crate kadouchi;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::process;
+
+use failure::Error;
+
+use kadouchi::{CellReport, ErrorLimit, InvalidDataError, LineReport, SearchPath, Visitor};
+
+const USAGE: &str = "\
+Usage:
+    kadouchi [options] check <schema.ku>                Parses, name-resolves and typechecks a schema.
+    kadouchi [options] validate <schema.ku> <data.tsv>  Validates a data file against a schema.
+    kadouchi [options] convert <schema.ku> <data.tsv> [--to json|ndjson]
+                                                        Converts a data file to JSON or to
+                                                        newline-delimited JSON.
+    kadouchi [options] codegen <schema.ku>              Prints Rust types and a parser for the data
+                                                        files of a schema.
+    kadouchi [options] dump <schema.ku>                 Prints the resolved item tree of a schema.
+
+Options:
+    -L, --lib-path <dir>  Looks up libraries and their native companions from <dir>
+                          before KADOUCHI_PATH and the bundled standard library.
+                          Can be given multiple times.
+    --max-errors <n>      Stops validating after <n> mismatches, or never for 0.
+    --to <format>         The output format of convert: json (the default) or ndjson.
+
+Exit codes:
+    0    Success
+    1    I/O or other failure
+    2    Invalid invocation
+    3    Syntax error
+    4    Name resolution error
+    5    Type error
+    6    The data doesn't match the schema";
+
+const EXIT_USAGE: i32 = 2;
+
+/// Data files are read in large chunks for throughput.
+const READ_BUFFER_SIZE: usize = 1 << 20;
+
+#[derive(Debug, Fail)]
+#[fail(display = "{}", _0)]
+struct UsageError(String);
+
+fn check(schema: &Path, search_path: &SearchPath) -> Result<(), Error> {
+    let mut bytestore = Vec::new();
+    kadouchi::parse_with_search_path(schema, search_path, &mut bytestore)?;
+    println!("{}: ok", schema.display());
+    Ok(())
+}
+
+/// Prints the mismatches as they are found.
+struct Printer<'a, W> {
+    data: &'a Path,
+    out: W,
+    mismatches: usize,
+}
+
+impl<'a, W: Write> Visitor for Printer<'a, W> {
+    fn visit_row(&mut self, cells: Vec<CellReport>) -> Result<(), Error> {
+        for cell in cells.iter().filter(|c| !c.matches) {
+            self.mismatches += 1;
+            write!(
+                self.out,
+                "{}: line {}, column {}: {:?} doesn't match {}",
+                self.data.display(),
+                cell.line,
+                cell.column,
+                cell.content,
+                cell.expected
+            )?;
+            if let Some(ref reason) = cell.reason {
+                write!(self.out, ": {}", reason)?;
+            }
+            match cell.closest {
+                Some(ref closest) => writeln!(self.out, " (closest alternative: {:?})", closest)?,
+                None => writeln!(self.out)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_line(&mut self, line: LineReport) -> Result<(), Error> {
+        self.mismatches += 1;
+        writeln!(self.out, "{}: {}", self.data.display(), line)?;
+        Ok(())
+    }
+}
+
+fn validate(schema: &Path, data: &Path, options: &Options) -> Result<(), Error> {
+    let mut bytestore = Vec::new();
+    let root = kadouchi::parse_with_search_path(schema, &options.search_path, &mut bytestore)?;
+    let (dialect, layout) = kadouchi::file_layout(&root)?;
+    let reader = BufReader::with_capacity(READ_BUFFER_SIZE, File::open(data)?);
+
+    let stdout = io::stdout();
+    let mut printer = Printer {
+        data,
+        out: BufWriter::new(stdout.lock()),
+        mismatches: 0,
+    };
+    match options.max_errors {
+        Some(limit) => {
+            let mut limited = ErrorLimit::new(&mut printer, limit);
+            kadouchi::validate_reader(&dialect, &layout, reader, &mut limited)?
+        }
+        None => kadouchi::validate_reader(&dialect, &layout, reader, &mut printer)?,
+    }
+
+    if printer.mismatches == 0 {
+        writeln!(printer.out, "{}: ok", data.display())?;
+    }
+    printer.out.flush()?;
+
+    if printer.mismatches == 0 {
+        Ok(())
+    } else {
+        Err(InvalidDataError(printer.mismatches).into())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Format {
+    Json,
+    Ndjson,
+}
+
+fn parse_format(value: &str) -> Result<Format, Error> {
+    match value {
+        "json" => Ok(Format::Json),
+        "ndjson" => Ok(Format::Ndjson),
+        _ => Err(UsageError(format!("--to expects json or ndjson, got {:?}", value)).into()),
+    }
+}
+
+fn convert(schema: &Path, data: &Path, options: &Options) -> Result<(), Error> {
+    let mut bytestore = Vec::new();
+    let root = kadouchi::parse_with_search_path(schema, &options.search_path, &mut bytestore)?;
+    let (dialect, layout) = kadouchi::file_layout(&root)?;
+    let reader = BufReader::with_capacity(READ_BUFFER_SIZE, File::open(data)?);
+
+    let stdout = io::stdout();
+    let mut writer = BufWriter::new(stdout.lock());
+    match options.format {
+        Format::Json => {
+            let data = kadouchi::parse_reader(&dialect, &layout, reader)?;
+            kadouchi::write_json(&data, &mut writer)?;
+        }
+        // The records are written as they are parsed
+        Format::Ndjson => {
+            let mut records = kadouchi::NdjsonWriter::new(&mut writer);
+            kadouchi::parse_records(&dialect, &layout, reader, &mut records)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn codegen(schema: &Path, search_path: &SearchPath) -> Result<(), Error> {
+    let mut bytestore = Vec::new();
+    let root = kadouchi::parse_with_search_path(schema, search_path, &mut bytestore)?;
+    print!("{}", kadouchi::generate_rust(&root)?);
+    Ok(())
+}
+
+fn dump(schema: &Path, search_path: &SearchPath) -> Result<(), Error> {
+    let mut bytestore = Vec::new();
+    let root = kadouchi::parse_with_search_path(schema, search_path, &mut bytestore)?;
+    println!("{:#?}", root);
+    Ok(())
+}
+
+struct Options {
+    search_path: SearchPath,
+    /// Stops the validation after this many mismatches
+    max_errors: Option<usize>,
+    /// The output format of `convert`
+    format: Format,
+}
+
+fn parse_max_errors(value: &str) -> Result<usize, Error> {
+    value
+        .parse()
+        .map_err(|_| UsageError(format!("--max-errors expects a number, got {:?}", value)).into())
+}
+
+/// Splits the options from the arguments. The library directories given as
+/// options take precedence over the default search path.
+fn parse_options(args: &[String]) -> Result<(Vec<String>, Options), Error> {
+    let mut lib_dirs = Vec::new();
+    let mut max_errors = None;
+    let mut format = Format::Json;
+    let mut rest = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "-L" || arg == "--lib-path" {
+            match args.next() {
+                Some(dir) => lib_dirs.push(dir.as_str()),
+                None => return Err(UsageError(format!("{} expects a directory", arg)).into()),
+            }
+        } else if arg.starts_with("--lib-path=") {
+            lib_dirs.push(&arg["--lib-path=".len()..]);
+        } else if arg == "--max-errors" {
+            match args.next() {
+                Some(value) => max_errors = Some(parse_max_errors(value)?),
+                None => return Err(UsageError(format!("{} expects a number", arg)).into()),
+            }
+        } else if arg.starts_with("--max-errors=") {
+            max_errors = Some(parse_max_errors(&arg["--max-errors=".len()..])?);
+        } else if arg == "--to" {
+            match args.next() {
+                Some(value) => format = parse_format(value)?,
+                None => return Err(UsageError(format!("{} expects a format", arg)).into()),
+            }
+        } else if arg.starts_with("--to=") {
+            format = parse_format(&arg["--to=".len()..])?;
+        } else {
+            rest.push(arg.clone());
+        }
+    }
+
+    let mut search_path = SearchPath::from_env();
+    for dir in lib_dirs.into_iter().rev() {
+        search_path.push_front(dir);
+    }
+
+    Ok((
+        rest,
+        Options {
+            search_path,
+            max_errors,
+            format,
+        },
+    ))
+}
+
+fn run(args: &[String]) -> Result<(), Error> {
+    let (args, options) = parse_options(args)?;
+    let search_path = &options.search_path;
+
+    let command = match args.first() {
+        Some(command) => command.as_str(),
+        None => return Err(UsageError("No command given".to_owned()).into()),
+    };
+
+    match (command, args.len()) {
+        ("check", 2) => check(Path::new(&args[1]), search_path),
+        ("dump", 2) => dump(Path::new(&args[1]), search_path),
+        ("codegen", 2) => codegen(Path::new(&args[1]), search_path),
+        ("validate", 3) => validate(Path::new(&args[1]), Path::new(&args[2]), &options),
+        ("convert", 3) => convert(Path::new(&args[1]), Path::new(&args[2]), &options),
+        ("check", _) | ("dump", _) | ("codegen", _) => {
+            Err(UsageError(format!("{} expects exactly one schema file", command)).into())
+        }
+        ("validate", _) | ("convert", _) => Err(UsageError(format!(
+            "{} expects a schema file and a data file",
+            command
+        )).into()),
+        _ => Err(UsageError(format!("Unknown command {}", command)).into()),
+    }
+}
+
 fn main() {
-    println!("Hello, world!");
+    env_logger::init();
+
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+
+    if let Err(err) = run(&args) {
+        eprintln!("error: {}", err);
+        let code = if err.downcast_ref::<UsageError>().is_some() {
+            eprintln!("\n{}", USAGE);
+            EXIT_USAGE
+        } else {
+            kadouchi::exit_code(&err)
+        };
+        process::exit(code);
+    }
 }
@@ -1,4 +1,4 @@
-use std::collections::HashMap as Map;
+use std::collections::{HashMap as Map, HashSet};
 use std::fmt;
 
 use failure::Error;
@@ -7,14 +7,19 @@ use scoped_stack::Stack;
 use KEYWORD_EXPORT;
 
 use errors::{
-    InvalidExportError, PathResolutionError, PrivacyError, ShadowingError, UnknownNameError,
+    AmbiguityError, DuplicateExportError, EarlyExportError, InvalidExportError, InvalidUseError,
+    PathResolutionError, PrivacyError, ReferenceCycleError, ShadowingError, UnknownNameError,
 };
-use tokens::{Call, Exp, Lit, Path as RelPath};
+use span::{Snippet, Span};
+use tokens::{Call, Doc, Exp, Lit, Path as RelPath, Use, UseTree};
 
 #[derive(Clone, Eq, PartialEq)]
 pub struct Namespace<'a> {
     pub local: Map<&'a str, usize>,
     pub items: Vec<Item<'a>>,
+    /// The names imported by globs and the items they refer to. Local names
+    /// take precedence over them.
+    pub globs: Map<&'a str, Vec<AbsPath2>>,
 }
 
 #[derive(Clone, Eq, PartialEq)]
@@ -25,6 +30,8 @@ pub struct Item<'a> {
     pub ns: Namespace<'a>,
     pub referent: Option<AbsPath2>,
     pub literal: Option<Lit<'a>>,
+    pub span: Option<Span>,
+    pub doc: Option<Doc<'a>>,
 }
 
 impl<'a> Item<'a> {
@@ -36,6 +43,8 @@ impl<'a> Item<'a> {
             local_name: None,
             referent: None,
             literal: None,
+            span: None,
+            doc: None,
         }
     }
 
@@ -47,6 +56,8 @@ impl<'a> Item<'a> {
             local_name: Some(name),
             referent: None,
             literal: None,
+            span: None,
+            doc: None,
         }
     }
 
@@ -119,6 +130,12 @@ impl<'a> fmt::Debug for Item<'a> {
         self.path.fmt(formatter)?;
         formatter.write_str(" ")?;
 
+        if let Some(ref doc) = self.doc {
+            formatter.write_str("Doc: ")?;
+            doc.text().fmt(formatter)?;
+            formatter.write_str(" ")?;
+        }
+
         if let Some(ref r) = self.referent {
             formatter.write_str("Refers: ")?;
             r.fmt(formatter)?;
@@ -154,6 +171,7 @@ impl<'a> Namespace<'a> {
         Self {
             local: Map::new(),
             items: Vec::new(),
+            globs: Map::new(),
         }
     }
 }
@@ -180,7 +198,7 @@ impl<'a> fmt::Debug for Namespace<'a> {
     }
 }
 
-#[derive(Clone, Eq, PartialEq)]
+#[derive(Clone, Eq, Hash, PartialEq)]
 pub struct AbsPath2 {
     inner: Vec<usize>,
 }
@@ -226,45 +244,191 @@ impl fmt::Debug for AbsPath2 {
     }
 }
 
-fn handle_export<'a>(call: &Call<'a>, ns: &mut Namespace<'a>) -> Result<(), InvalidExportError> {
-    if call.path.only_segment() == Some(KEYWORD_EXPORT) {
-        for exported_item in &call.args {
-            if let Some(name) = exported_item.bound_name() {
-                if let Some(idx) = ns.local.get(name) {
-                    ns.items[*idx].exported = true;
-                } else {
-                    return Err(InvalidExportError(name.to_string()));
-                }
-            } else {
-                // Syntactic sugar: if it's a local name, you don't need as
-                if let Some(simple_name) = exported_item.call().and_then(|c| c.path.only_segment())
-                {
-                    if let Some(idx) = ns.local.get(simple_name) {
-                        ns.items[*idx].exported = true;
-                    } else {
-                        return Err(InvalidExportError(simple_name.to_string()));
+/// Handles an `export(...)` at the index `export_idx` of `parent`. A name
+/// makes an item defined before the export public, and `path as name`
+/// re-exports the item of the path under a new name, as does the name of an
+/// item imported with a glob. The items declared along with the export end
+/// at `declared_end`, and the re-exported ones are added after them.
+fn handle_export<'a>(
+    call: &Call<'a>,
+    export_idx: usize,
+    declared_end: usize,
+    parent: &mut Item<'a>,
+    root: &Item<'a>,
+) -> Result<(), Error> {
+    if call.path.only_segment() != Some(KEYWORD_EXPORT) {
+        return Ok(());
+    }
+    let is_early = |idx: usize| idx > export_idx && idx < declared_end;
+    for (arg_idx, exported_item) in call.args.iter().enumerate() {
+        let span = exported_item.span();
+        let path = match exported_item.call().filter(|c| c.args.is_empty()) {
+            Some(exported_call) => &exported_call.path,
+            None => {
+                return Err(InvalidExportError(call.path.to_string(), Snippet::new(span)).into())
+            }
+        };
+
+        match (exported_item.bound_name(), path.only_segment()) {
+            (Some(name), head) if head != Some(name) => {
+                if let Some(idx) = parent.ns.local.get(path.head()) {
+                    if is_early(*idx) {
+                        let snippet = Snippet::new(span);
+                        return Err(EarlyExportError(path.head().to_owned(), snippet).into());
                     }
-                } else {
-                    return Err(InvalidExportError(call.path.to_string()));
                 }
+                if parent.ns.local.contains_key(name) {
+                    let snippet = Snippet::new(Span::of(name));
+                    return Err(ShadowingError(name.to_owned(), snippet).into());
+                }
+
+                // The re-exported item is an alias of the argument of the export
+                let mut referent = parent.path.clone();
+                referent.push_segment(export_idx);
+                referent.push_segment(arg_idx);
+                reexport(parent, name, referent, exported_item);
             }
+            (_, Some(name)) => match parent.ns.local.get(name).cloned() {
+                Some(idx) => {
+                    if parent.ns.items[idx].exported {
+                        let snippet = Snippet::new(span);
+                        return Err(DuplicateExportError(name.to_owned(), snippet).into());
+                    }
+                    if is_early(idx) {
+                        return Err(EarlyExportError(name.to_owned(), Snippet::new(span)).into());
+                    }
+                    parent.ns.items[idx].exported = true;
+                }
+                None => {
+                    // An item imported with a glob is re-exported under its name
+                    let referent = match parent.ns.globs.get(name) {
+                        Some(paths) => glob_referent(name, paths, root)?.path.clone(),
+                        None => {
+                            return Err(InvalidExportError(name.to_owned(), Snippet::new(span)).into())
+                        }
+                    };
+                    reexport(parent, name, referent, exported_item);
+                }
+            },
+            _ => return Err(InvalidExportError(path.to_string(), Snippet::new(span)).into()),
         }
     }
     Ok(())
 }
 
+/// Adds an exported alias of `referent` named `name` to `parent`.
+fn reexport<'a>(
+    parent: &mut Item<'a>,
+    name: &'a str,
+    referent: AbsPath2,
+    exported_item: &Exp<'a>,
+) {
+    let mut item = Item::named(name);
+    item.referent = Some(referent);
+    item.exported = true;
+    item.span = Some(exported_item.span());
+    item.doc = exported_item.doc().cloned();
+    item.path = parent.path.clone();
+    item.path.push_segment(parent.next_idx());
+    parent.add_child(item);
+}
+
+/// Follows the referents of plain aliases like `std.str as str` to the item
+/// they stand for. The referents in the library being resolved, which isn't
+/// in the tree yet, are not followed.
+fn unalias<'a, 'str>(root: &'a Item<'str>, mut item: &'a Item<'str>) -> &'a Item<'str> {
+    while let Some(ref referent) = item.referent {
+        let in_tree = referent
+            .iter_segments()
+            .next()
+            .map_or(false, |lib| lib < root.ns.items.len());
+        if !item.ns.items.is_empty() || item.literal.is_some() || !in_tree {
+            break;
+        }
+        item = root.traverse_path(referent);
+    }
+    item
+}
+
+fn canonical<'a, 'str>(root: &'a Item<'str>, item: &'a Item<'str>) -> &'a AbsPath2 {
+    &unalias(root, item).path
+}
+
+/// The dotted names of the items along an absolute path.
+fn path_name(root: &Item, path: &AbsPath2) -> String {
+    let mut item = root;
+    let mut names = Vec::new();
+    for idx in path.iter_segments() {
+        item = &item.ns.items[idx];
+        names.push(item.local_name.unwrap_or("(anon)"));
+    }
+    names.join(".")
+}
+
+/// Picks the item that a glob-imported name refers to. The name is ambiguous
+/// if the globs import different items under it.
+fn glob_referent<'a, 'str>(
+    name: &'str str,
+    paths: &[AbsPath2],
+    root: &'a Item<'str>,
+) -> Result<&'a Item<'str>, AmbiguityError> {
+    let first = root.traverse_path(&paths[0]);
+    for path in &paths[1..] {
+        if canonical(root, first) != canonical(root, root.traverse_path(path)) {
+            return Err(AmbiguityError(
+                name.to_owned(),
+                path_name(root, &paths[0]),
+                path_name(root, path),
+                Snippet::new(Span::of(name)),
+            ));
+        }
+    }
+    Ok(first)
+}
+
+/// Checks that an item found from the root scope, which holds the libraries,
+/// is one of the `libraries` in the scope of the library being resolved.
+fn check_library<'str>(
+    name: &'str str,
+    found: &Item<'str>,
+    libraries: &[&str],
+) -> Result<(), UnknownNameError> {
+    if found.path.iter_segments().count() == 1 && !libraries.contains(&name) {
+        return Err(UnknownNameError(name.to_owned(), Snippet::new(Span::of(name))));
+    }
+    Ok(())
+}
+
+/// The root of the tree, which is always the outermost scope.
+fn root_of<'a, 'str>(scopes: &'a Stack<&'a Item<'str>>) -> &'a Item<'str> {
+    scopes
+        .iter_frames()
+        .filter_map(|frame| frame.peek())
+        .last()
+        .expect("Assert: the root is always in scope.")
+}
+
+/// Searches for the item called `name` from the scopes, innermost first. An
+/// item doesn't see itself, so `format as format` refers to an outer `format`.
 fn find_referent<'a, 'str: 'a>(
     name: &'str str,
     scopes: &'a Stack<&'a Item<'str>>,
-) -> Result<(&'a Item<'str>, &'a Stack<'a, &'a Item<'str>>), UnknownNameError> {
+    referrer: Option<&AbsPath2>,
+) -> Result<(&'a Item<'str>, &'a Stack<'a, &'a Item<'str>>), Error> {
     for frame in scopes.iter_frames() {
         if let Some(item) = frame.peek() {
             if let Some(idx) = item.ns.local.get(name) {
-                return Ok((&item.ns.items[*idx], frame));
+                let found = &item.ns.items[*idx];
+                if Some(&found.path) != referrer {
+                    return Ok((found, frame));
+                }
+            }
+            if let Some(paths) = item.ns.globs.get(name) {
+                return Ok((glob_referent(name, paths, root_of(scopes))?, frame));
             }
         }
     }
-    Err(UnknownNameError(name.to_owned()))
+    Err(UnknownNameError(name.to_owned(), Snippet::new(Span::of(name))).into())
 }
 
 #[test]
@@ -294,96 +458,215 @@ fn test_find_referent() {
     let scopes_3 = scopes_2.push(&root.ns.items[0].ns.items[0]); // item_c
     let scopes_4 = scopes_3.push(&root.ns.items[0].ns.items[0].ns.items[0]); // item_d
 
-    find_referent("a", &scopes_4).unwrap();
-    find_referent("b", &scopes_4).unwrap();
-    find_referent("c", &scopes_4).unwrap();
-    find_referent("d", &scopes_4).unwrap();
+    find_referent("a", &scopes_4, None).unwrap();
+    find_referent("b", &scopes_4, None).unwrap();
+    find_referent("c", &scopes_4, None).unwrap();
+    find_referent("d", &scopes_4, None).unwrap();
 
-    assert!(find_referent("no", &scopes_4).is_err());
-    assert!(find_referent("root", &scopes_4).is_err());
-    assert!(find_referent("e", &scopes_4).is_err());
+    assert!(find_referent("no", &scopes_4, None).is_err());
+    assert!(find_referent("root", &scopes_4, None).is_err());
+    assert!(find_referent("e", &scopes_4, None).is_err());
 
-    let (item, scope) = find_referent("c", &scopes_4).unwrap();
+    let (item, scope) = find_referent("c", &scopes_4, None).unwrap();
 
     assert_eq!(item, &root.ns.items[0].ns.items[0].ns.items[0]);
 
     assert_eq!(scope, &scopes_3);
 }
 
+/// Walks a path from the item of its first segment, following the aliases
+/// along it. The items on the way must be exported unless they are in the
+/// library of `referrer`.
 fn walk_path<'a, 'str, 'scope>(
     path: &'a RelPath<'str>,
     mut item: &'scope Item<'str>,
+    root: &'scope Item<'str>,
+    referrer: &AbsPath2,
     abs_path: &mut AbsPath2,
 ) -> Result<&'scope Item<'str>, Error> {
+    let library = referrer.iter_segments().next();
     let mut path_iter = path.0.iter();
     path_iter
         .next()
         .expect("Assert: path always has at least one segment.");
 
     for segment in path_iter {
+        item = unalias(root, item);
+        *abs_path = item.path.clone();
         if let Some(idx) = item.ns.local.get(segment.0) {
-            if item.ns.items[*idx].exported {
-                item = &item.ns.items[*idx];
+            let child = &item.ns.items[*idx];
+            if child.exported || child.path.iter_segments().next() == library {
+                item = child;
                 abs_path.push_segment(*idx);
             } else {
-                return Err(PrivacyError(segment.0.to_owned()).into());
+                return Err(PrivacyError(segment.0.to_owned(), Snippet::new(segment.span())).into());
             }
         } else {
-            return Err(PathResolutionError(segment.0.to_owned()).into());
+            return Err(
+                PathResolutionError(segment.0.to_owned(), Snippet::new(segment.span())).into(),
+            );
         }
     }
 
     Ok(item)
 }
 
-fn resolve_recursive<'a, 'str: 'a, 'ns>(
+/// The first pass of name resolution: adds the items of the expressions to
+/// `parent`, recursively, so that the names can be referred to before their
+/// definitions. The exports are handled once all the names are known.
+fn declare_recursive<'a, 'str: 'a>(
     token_tree: &'a [Exp<'str>],
-    scopes: Stack<&'ns Item<'str>>,
     parent: &mut Item<'str>,
     current_path: &mut AbsPath2,
+    root: &Item<'str>,
 ) -> Result<(), Error> {
+    let first = parent.next_idx();
     for token in token_tree {
+        if let Some(decl) = token.use_decl() {
+            return Err(InvalidUseError(Snippet::new(decl.span())).into());
+        }
+
         let mut item = if let Some(name) = token.bound_name() {
             if parent.ns.local.get(name).is_some() {
-                return Err(ShadowingError(name.to_owned()).into());
+                return Err(ShadowingError(name.to_owned(), Snippet::new(Span::of(name))).into());
             }
             Item::named(name)
         } else {
             Item::anon()
         };
 
+        if let Some(lit) = token.lit() {
+            item.set_lit(lit);
+        }
+        item.span = Some(token.span());
+        item.doc = token.doc().cloned();
+        current_path.push_segment(parent.next_idx());
+        item.path = current_path.clone();
+        declare_recursive(token.call_args(), &mut item, current_path, root)?;
+        trace!("Adding a child {:?} to parent {:?}", item, parent);
+        parent.add_child(item);
+        current_path.pop_segment();
+    }
+
+    let declared_end = parent.next_idx();
+    for (offset, token) in token_tree.iter().enumerate() {
         if let Some(call) = token.call() {
-            let scopes = scopes.push(&parent);
+            handle_export(call, first + offset, declared_end, parent, root)?;
+        }
+    }
+    Ok(())
+}
 
+/// The second pass of name resolution: finds the referents of the items
+/// declared from the expressions, which start at the index `first` of
+/// `parent`. The imported items are before them and the re-exported ones after.
+fn find_referents<'a, 'str: 'a, 'ns>(
+    token_tree: &'a [Exp<'str>],
+    first: usize,
+    scopes: Stack<&'ns Item<'str>>,
+    parent: &'ns Item<'str>,
+    libraries: &[&str],
+    referents: &mut Vec<(AbsPath2, AbsPath2)>,
+) -> Result<(), Error> {
+    for (token, item) in token_tree.iter().zip(&parent.ns.items[first..]) {
+        let scopes = scopes.push(parent);
+
+        if let Some(call) = token.call() {
             // Searches for the referent item from the surrounding scopes using the first segment of the path
-            let (base_referent, _scope) = find_referent(call.path.head(), &scopes)?;
+            let (base_referent, _scope) =
+                find_referent(call.path.head(), &scopes, Some(&item.path))?;
+            check_library(call.path.head(), base_referent, libraries)?;
 
             let mut referent_path = base_referent.path.clone();
 
             // Walks the path while visiting recursively the inner namespaces of the item
             // Checks if the path points to a valid and accessible (exported) item.
-            walk_path(&call.path, &base_referent, &mut referent_path)?;
+            walk_path(
+                &call.path,
+                &base_referent,
+                root_of(&scopes),
+                &item.path,
+                &mut referent_path,
+            )?;
+
+            referents.push((item.path.clone(), referent_path));
+        }
 
-            // Checks if the current item is an export command
-            handle_export(call, &mut parent.ns)?;
+        find_referents(token.call_args(), 0, scopes, item, libraries, referents)?;
+    }
+    Ok(())
+}
+
+fn resolve_recursive<'a, 'str: 'a, 'ns>(
+    token_tree: &'a [Exp<'str>],
+    scopes: Stack<&'ns Item<'str>>,
+    parent: &mut Item<'str>,
+    current_path: &mut AbsPath2,
+    libraries: &[&str],
+) -> Result<(), Error> {
+    let first = parent.next_idx();
+    declare_recursive(token_tree, parent, current_path, root_of(&scopes))?;
+
+    let mut referents = Vec::new();
+    find_referents(token_tree, first, scopes, parent, libraries, &mut referents)?;
 
-            item.referent = Some(referent_path);
+    // The paths are absolute, but the parent may not be in the tree yet
+    let depth = parent.path.iter_segments().count();
+    for (path, referent) in referents {
+        let mut item = &mut *parent;
+        for idx in path.iter_segments().skip(depth) {
+            item = &mut item.ns.items[idx];
         }
+        item.referent = Some(referent);
+    }
+    Ok(())
+}
 
-        if let Some(lit) = token.lit() {
-            item.set_lit(lit);
+/// Checks that no item depends on itself through its referents and
+/// arguments, like `a` referring to `b` referring to `a`. Items outside of
+/// `lib` are in the libraries built before it, which can't refer back.
+fn check_cycles<'str>(lib: &Item<'str>) -> Result<(), ReferenceCycleError> {
+    fn visit<'a, 'str>(
+        lib: &'a Item<'str>,
+        item: &'a Item<'str>,
+        visiting: &mut Vec<&'a Item<'str>>,
+        done: &mut HashSet<&'a AbsPath2>,
+    ) -> Result<(), ReferenceCycleError> {
+        if done.contains(&item.path) {
+            return Ok(());
         }
-        current_path.push_segment(parent.next_idx());
-        item.path = current_path.clone();
-        resolve_recursive(
-            token.call_args(),
-            scopes.push(&parent),
-            &mut item,
-            current_path,
-        )?;
-        trace!("Adding a child {:?} to parent {:?}", item, parent);
-        parent.add_child(item);
-        current_path.pop_segment();
+        if let Some(start) = visiting.iter().position(|i| i.path == item.path) {
+            let mut names = visiting[start..]
+                .iter()
+                .filter_map(|i| i.local_name)
+                .collect::<Vec<_>>();
+            names.push(item.local_name.unwrap_or("(anon)"));
+            let last = visiting.last().expect("Assert: the cycle has at least one item.");
+            return Err(ReferenceCycleError(
+                names.join(" -> "),
+                last.span.map_or_else(Snippet::default, Snippet::new),
+            ));
+        }
+
+        visiting.push(item);
+        if let Some(ref referent) = item.referent {
+            let mut segments = referent.iter_segments();
+            if segments.next() == lib.path.iter_segments().next() {
+                let referent = segments.fold(lib, |item, idx| &item.ns.items[idx]);
+                visit(lib, referent, visiting, done)?;
+            }
+        }
+        for arg in &item.ns.items {
+            visit(lib, arg, visiting, done)?;
+        }
+        visiting.pop();
+        done.insert(&item.path);
+        Ok(())
+    }
+
+    let mut done = HashSet::new();
+    for item in &lib.ns.items {
+        visit(lib, item, &mut Vec::new(), &mut done)?;
     }
     Ok(())
 }
@@ -409,7 +692,9 @@ fn test_resolve_recursive_1() {
 
     let mut current_path = lib.path.clone();
 
-    resolve_recursive(&token_tree, scopes.push(&root), &mut lib, &mut current_path).unwrap();
+    let libraries = [KEYWORD_INTRINSIC];
+    resolve_recursive(&token_tree, scopes.push(&root), &mut lib, &mut current_path, &libraries)
+        .unwrap();
 
     assert_eq!(lib.ns.items.len(), 2);
 
@@ -420,6 +705,38 @@ fn test_resolve_recursive_1() {
     assert_eq!(lib.ns.items[1].referent, Some(AbsPath2::new(vec![1, 0])));
 }
 
+#[test]
+fn test_resolve_doc() {
+    use tokens;
+    use KEYWORD_INTRINSIC;
+    use KEYWORD_ROOT;
+
+    let token_tree: Vec<Exp<'static>> = tokens::parse_file(
+        r#"intrinsic("regexp") as regexp
+## Three a's
+regexp("aaa") as str"#,
+    ).unwrap();
+
+    let scopes = Stack::new();
+    let mut root = Item::named(KEYWORD_ROOT);
+
+    let mut intrinsic = Item::named(KEYWORD_INTRINSIC);
+    intrinsic.path = AbsPath2::new(vec![0]);
+    root.add_child(intrinsic);
+
+    let mut lib = Item::named("test_lib");
+    lib.path = AbsPath2::new(vec![root.next_idx()]);
+
+    let mut current_path = lib.path.clone();
+
+    let libraries = [KEYWORD_INTRINSIC];
+    resolve_recursive(&token_tree, scopes.push(&root), &mut lib, &mut current_path, &libraries)
+        .unwrap();
+
+    assert_eq!(lib.ns.items[0].doc, None);
+    assert_eq!(lib.ns.items[1].doc.as_ref().unwrap().text(), "Three a's");
+}
+
 #[test]
 fn test_resolve_recursive_2() {
     use parse_lib;
@@ -459,24 +776,104 @@ module(
     println!("{:#?}", root);
 }
 
-pub fn glob_import<'str>(root: &Item<'str>, source: &AbsPath2, target: &mut Item<'str>) {
-    let source_ns = &root.traverse_path(source).ns;
+/// Adds an item named `name` to `target` that refers to the item at `referent`.
+fn import<'str>(
+    target: &mut Item<'str>,
+    name: &'str str,
+    referent: AbsPath2,
+    span: Span,
+) -> Result<(), ShadowingError> {
+    if target.ns.local.contains_key(name) {
+        return Err(ShadowingError(name.to_owned(), Snippet::new(span)));
+    }
 
-    for (name, idx) in &source_ns.local {
-        // Get source item path
-        let mut source_item_path = source.clone();
-        source_item_path.push_segment(*idx);
+    let mut imported_item = Item::named(name);
+    imported_item.referent = Some(referent);
+    imported_item.span = Some(span);
+    imported_item.path = target.path.clone();
+    imported_item.path.push_segment(target.next_idx());
 
-        // Creating a new item to the target namespace
-        let mut imported_item = Item::named(name);
-        imported_item.referent = Some(source_item_path);
-        imported_item.path = target.path.clone();
-        imported_item.path.push_segment(target.next_idx());
+    target.add_child(imported_item);
+    Ok(())
+}
+
+/// The exported and named items of a namespace with their paths.
+fn exported_items<'a, 'str>(
+    source: &'a Item<'str>,
+    source_path: &'a AbsPath2,
+) -> impl Iterator<Item = (&'str str, AbsPath2)> + 'a {
+    source
+        .ns
+        .items
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| item.exported)
+        .filter_map(move |(idx, item)| {
+            let mut path = source_path.clone();
+            path.push_segment(idx);
+            item.local_name.map(|name| (name, path))
+        })
+}
 
-        target.add_child(imported_item);
+/// Imports every exported item of `source` into `target` as globs.
+pub fn glob_import<'str>(root: &Item<'str>, source: &AbsPath2, target: &mut Item<'str>) {
+    for (name, path) in exported_items(root.traverse_path(source), source) {
+        target.ns.globs.entry(name).or_insert_with(Vec::new).push(path);
     }
 }
 
+/// Resolves the items imported by a `use` declaration of the library at
+/// `referrer` to their local names. The path of a `use` starts from a library.
+fn resolve_use<'str>(
+    exp: &Exp<'str>,
+    decl: &Use<'str>,
+    root: &Item<'str>,
+    referrer: &AbsPath2,
+    libraries: &[&str],
+) -> Result<Vec<(&'str str, AbsPath2, Span)>, Error> {
+    let scopes = Stack::new();
+    let scopes = scopes.push(root);
+    let (base, _scope) = find_referent(decl.path.head(), &scopes, None)?;
+    check_library(decl.path.head(), base, libraries)?;
+    let mut path = base.path.clone();
+    let source = walk_path(&decl.path, base, root, referrer, &mut path)?;
+
+    // The items of an alias are those of the module it refers to
+    let source = unalias(root, source);
+
+    let imports = match &decl.tree {
+        UseTree::Single => {
+            let last = decl.path.0.last().expect("Assert: path always has at least one segment.");
+            vec![(exp.bound_name().unwrap_or(last.0), path, exp.span())]
+        }
+        UseTree::Glob(star) => exported_items(source, &source.path)
+            .map(|(name, path)| (name, path, star.span()))
+            .collect(),
+        UseTree::List(names) => {
+            let mut imports = Vec::new();
+            for name in names {
+                let sym = &name.0;
+                let idx = match source.ns.local.get(sym.0) {
+                    Some(idx) => *idx,
+                    None => {
+                        return Err(
+                            PathResolutionError(sym.0.to_owned(), Snippet::new(sym.span())).into(),
+                        )
+                    }
+                };
+                if !source.ns.items[idx].exported {
+                    return Err(PrivacyError(sym.0.to_owned(), Snippet::new(sym.span())).into());
+                }
+                let mut item_path = source.path.clone();
+                item_path.push_segment(idx);
+                imports.push((name.bound_name(), item_path, name.span()));
+            }
+            imports
+        }
+    };
+    Ok(imports)
+}
+
 #[test]
 fn test_glob_import() {
     use KEYWORD_ROOT;
@@ -490,24 +887,35 @@ fn test_glob_import() {
 
     let mut prelude_item = Item::named("prelude_item");
     prelude_item.path = AbsPath2::new(vec![0, 0]);
+    prelude_item.exported = true;
     prelude.add_child(prelude_item);
 
+    let mut private_item = Item::named("private_item");
+    private_item.path = AbsPath2::new(vec![0, 1]);
+    prelude.add_child(private_item);
+
     root.add_child(prelude);
 
     let mut lib = Item::named("lib");
 
     glob_import(&root, &prelude_path, &mut lib);
 
-    assert_eq!(lib.ns.local.len(), 1);
+    assert_eq!(lib.ns.globs.len(), 1);
 
-    assert!(lib.ns.item("prelude_item").is_some());
+    assert_eq!(lib.ns.globs["prelude_item"], [AbsPath2::new(vec![0, 0])]);
+    assert!(lib.ns.globs.get("private_item").is_none());
 }
 
+/// Resolves the names of a library. The prelude and the `use` declarations
+/// are imported before the items of the library are resolved. Of the
+/// libraries in the root scope, only `libraries` are visible.
 pub fn resolve<'a, 'str>(
     libname: &'str str,
+    uses: &'a [Exp<'str>],
     token_tree: &'a [Exp<'str>],
     root: &'a Item<'str>,
     prelude_path: Option<&AbsPath2>,
+    libraries: &[&str],
 ) -> Result<Item<'str>, Error> {
     let scopes = Stack::new();
     let mut lib = Item::named(libname);
@@ -518,6 +926,19 @@ pub fn resolve<'a, 'str>(
     if let Some(prelude_path) = prelude_path {
         glob_import(&root, prelude_path, &mut lib);
     }
-    resolve_recursive(token_tree, scopes.push(root), &mut lib, &mut current_path)?;
+    for exp in uses {
+        let decl = exp
+            .use_decl()
+            .expect("Assert: only use declarations are imported.");
+        for (name, referent, span) in resolve_use(exp, decl, root, &lib.path, libraries)? {
+            if let UseTree::Glob(_) = decl.tree {
+                lib.ns.globs.entry(name).or_insert_with(Vec::new).push(referent);
+            } else {
+                import(&mut lib, name, referent, span)?;
+            }
+        }
+    }
+    resolve_recursive(token_tree, scopes.push(root), &mut lib, &mut current_path, libraries)?;
+    check_cycles(&lib)?;
     Ok(lib)
 }
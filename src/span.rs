@@ -0,0 +1,182 @@
+use std::fmt;
+use std::str::from_utf8;
+
+/// A range of byte positions in the source code. The source texts stay in
+/// memory during the whole compilation, so the addresses of the slices parsed
+/// from them are used as positions; they are unique across files and
+/// `SourceMap` resolves them back to a file, line and column.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Span {
+    pub lo: usize,
+    pub hi: usize,
+}
+
+impl Span {
+    pub fn of(slice: &str) -> Self {
+        let lo = slice.as_ptr() as usize;
+        Span {
+            lo,
+            hi: lo + slice.len(),
+        }
+    }
+
+    pub fn to(self, other: Span) -> Self {
+        Span {
+            lo: self.lo.min(other.lo),
+            hi: self.hi.max(other.hi),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+pub struct SourceMap<'a> {
+    files: Vec<(String, &'a str)>,
+}
+
+impl<'a> SourceMap<'a> {
+    pub fn new() -> Self {
+        SourceMap { files: Vec::new() }
+    }
+
+    /// Adds a source file. Files that aren't valid UTF-8 are never parsed, so
+    /// they are skipped.
+    pub fn add(&mut self, name: &str, bytes: &'a [u8]) {
+        if let Ok(text) = from_utf8(bytes) {
+            self.files.push((name.to_owned(), text));
+        }
+    }
+
+    fn find(&self, span: Span) -> Option<(&str, &'a str, usize)> {
+        self.files
+            .iter()
+            .filter_map(|(name, text)| {
+                let base = text.as_ptr() as usize;
+                if base <= span.lo && span.lo <= base + text.len() {
+                    Some((name.as_str(), *text, span.lo - base))
+                } else {
+                    None
+                }
+            })
+            .next()
+    }
+
+    pub fn lookup(&self, span: Span) -> Option<Location> {
+        let (name, text, offset) = self.find(span)?;
+        let line_start = text[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+        Some(Location {
+            file: name.to_owned(),
+            line: text[..offset].matches('\n').count() + 1,
+            column: text[line_start..offset].chars().count() + 1,
+        })
+    }
+
+    /// Renders the location and the source line of a span, with the spanned
+    /// part underlined.
+    pub fn render(&self, span: Span) -> Option<String> {
+        let location = self.lookup(span)?;
+        let (_, text, offset) = self.find(span)?;
+
+        let line_start = text[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = text[offset..]
+            .find('\n')
+            .map(|i| offset + i)
+            .unwrap_or(text.len());
+        let underline_end = (offset + span.hi.saturating_sub(span.lo)).min(line_end);
+
+        let line_text = text[line_start..line_end].trim_right_matches('\r');
+        let padding = text[line_start..offset]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let carets = text[offset..underline_end].chars().count().max(1);
+
+        let line_number = location.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+
+        Some(format!(
+            "{gutter}--> {}:{}:{}\n{gutter} |\n{} | {}\n{gutter} | {}{}",
+            location.file,
+            location.line,
+            location.column,
+            line_number,
+            line_text,
+            padding,
+            "^".repeat(carets),
+            gutter = gutter,
+        ))
+    }
+}
+
+/// The source snippet shown by an error. It's rendered once the sources of
+/// the spanned code are known.
+#[derive(Clone, Debug, Default)]
+pub struct Snippet {
+    span: Option<Span>,
+    rendered: Option<String>,
+}
+
+impl Snippet {
+    pub fn new(span: Span) -> Self {
+        Snippet {
+            span: Some(span),
+            rendered: None,
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    pub fn set_span(&mut self, span: Span) {
+        if self.span.is_none() {
+            self.span = Some(span);
+        }
+    }
+
+    pub fn locate(&mut self, sources: &SourceMap) {
+        if self.rendered.is_none() {
+            self.rendered = self.span.and_then(|span| sources.render(span));
+        }
+    }
+}
+
+impl fmt::Display for Snippet {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref rendered) = self.rendered {
+            formatter.write_str("\n")?;
+            formatter.write_str(rendered)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_render_snippet() {
+    let source = "first line\n\tsecond line\n";
+    let mut sources = SourceMap::new();
+    sources.add("test.ku", source.as_bytes());
+
+    let span = Span::of(&source[12..18]);
+
+    assert_eq!(
+        sources.lookup(span),
+        Some(Location {
+            file: "test.ku".to_owned(),
+            line: 2,
+            column: 2,
+        })
+    );
+    assert_eq!(
+        sources.render(span).unwrap(),
+        " --> test.ku:2:2\n  |\n2 | \tsecond line\n  | \t^^^^^^"
+    );
+
+    assert_eq!(sources.lookup(Span::of("elsewhere")), None);
+}
@@ -1,13 +1,22 @@
+use std::borrow::Cow;
+use std::char;
 use std::fmt;
 
 use errors::SyntaxError;
 use nom::types::CompleteStr;
-use nom::{alpha1, alphanumeric1, digit1, recognize_float};
-use KEYWORD_AS;
+use nom::{self, alpha1, alphanumeric1, digit1, recognize_float, Context, ErrorKind, IResult};
+use span::{Snippet, Span};
+use {KEYWORD_AS, KEYWORD_USE};
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Sym<'a>(pub &'a str);
 
+impl<'a> Sym<'a> {
+    pub fn span(&self) -> Span {
+        Span::of(self.0)
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Path<'a>(pub Vec<Sym<'a>>);
 
@@ -24,6 +33,11 @@ impl<'a> Path<'a> {
         self.0[0].0
     }
 
+    pub fn span(&self) -> Span {
+        let last = self.0.last().expect("Assert: path always has at least one segment.");
+        self.0[0].span().to(last.span())
+    }
+
     pub fn to_string(&self) -> String {
         let mut out = String::new();
         for segment in &self.0 {
@@ -37,23 +51,25 @@ impl<'a> Path<'a> {
 
 #[derive(Eq, PartialEq, Clone)]
 pub enum Lit<'a> {
-    Str(&'a str),
+    /// The unescaped value of a string literal and its raw source text, quotes included.
+    Str(Cow<'a, str>, &'a str),
     Int(&'a str),
     Float(&'a str),
 }
 
+impl<'a> Lit<'a> {
+    pub fn span(&self) -> Span {
+        match self {
+            Lit::Str(_, raw) | Lit::Int(raw) | Lit::Float(raw) => Span::of(raw),
+        }
+    }
+}
+
 impl<'a> fmt::Debug for Lit<'a> {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
-            Lit::Str(s) => {
-                formatter.write_str("\"")?;
-                formatter.write_str(s)?;
-                formatter.write_str("\"")?;
-            }
-            Lit::Int(s) => formatter.write_str(s)?,
-            Lit::Float(s) => formatter.write_str(s)?,
+            Lit::Str(_, raw) | Lit::Int(raw) | Lit::Float(raw) => formatter.write_str(raw),
         }
-        Ok(())
     }
 }
 
@@ -63,42 +79,229 @@ pub struct Call<'a> {
     pub args: Vec<Exp<'a>>,
 }
 
+impl<'a> Call<'a> {
+    /// Spans from the path of the call to its last argument.
+    pub fn span(&self) -> Span {
+        match self.args.last() {
+            Some(arg) => self.path.span().to(arg.span()),
+            None => self.path.span(),
+        }
+    }
+}
+
+/// A name imported by a `use` declaration and the name it is bound to, if renamed.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct UseName<'a>(pub Sym<'a>, pub Option<Sym<'a>>);
+
+impl<'a> UseName<'a> {
+    pub fn bound_name(&self) -> &'a str {
+        self.1.as_ref().unwrap_or(&self.0).0
+    }
+
+    pub fn span(&self) -> Span {
+        match &self.1 {
+            Some(sym) => self.0.span().to(sym.span()),
+            None => self.0.span(),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum UseTree<'a> {
+    /// `use std.date.iso_hyphen`, which may be renamed with `as`.
+    Single,
+    /// `use std.date.*` imports every exported item.
+    Glob(Sym<'a>),
+    /// `use std.date.(iso_hyphen japanese_slash as slash)`
+    List(Vec<UseName<'a>>),
+}
+
+/// A declaration that imports items from a library into the scope of another.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Use<'a> {
+    pub keyword: Sym<'a>,
+    pub path: Path<'a>,
+    pub tree: UseTree<'a>,
+}
+
+impl<'a> Use<'a> {
+    pub fn span(&self) -> Span {
+        let end = match &self.tree {
+            UseTree::Single => self.path.span(),
+            UseTree::Glob(star) => star.span(),
+            UseTree::List(names) => names.last().map_or(self.path.span(), UseName::span),
+        };
+        self.keyword.span().to(end)
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum AnonExp<'a> {
     Call(Call<'a>),
     Literal(Lit<'a>),
+    Use(Use<'a>),
+}
+
+/// The lines of a doc comment, without the leading `##`.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Doc<'a>(pub Vec<&'a str>);
+
+impl<'a> Doc<'a> {
+    pub fn text(&self) -> String {
+        let lines = self
+            .0
+            .iter()
+            .map(|line| {
+                let line = line.trim_right_matches('\r');
+                if line.starts_with(' ') {
+                    &line[1..]
+                } else {
+                    line
+                }
+            })
+            .collect::<Vec<_>>();
+        lines.join("\n")
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
-pub struct Exp<'a>(AnonExp<'a>, Option<Sym<'a>>);
+pub struct Exp<'a>(AnonExp<'a>, Option<Sym<'a>>, Option<Doc<'a>>);
 
 impl<'a> Exp<'a> {
     pub fn bound_name(&self) -> Option<&'a str> {
         self.1.as_ref().map(|sym| sym.0)
     }
 
+    pub fn doc(&self) -> Option<&Doc<'a>> {
+        self.2.as_ref()
+    }
+
     pub fn call(&self) -> Option<&Call<'a>> {
         match &self.0 {
-            AnonExp::Literal(_) => None,
             AnonExp::Call(call) => Some(call),
+            AnonExp::Literal(_) | AnonExp::Use(_) => None,
         }
     }
 
     pub fn lit(&self) -> Option<&Lit<'a>> {
         match &self.0 {
             AnonExp::Literal(lit) => Some(lit),
-            AnonExp::Call(_) => None,
+            AnonExp::Call(_) | AnonExp::Use(_) => None,
+        }
+    }
+
+    pub fn use_decl(&self) -> Option<&Use<'a>> {
+        match &self.0 {
+            AnonExp::Use(decl) => Some(decl),
+            AnonExp::Call(_) | AnonExp::Literal(_) => None,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        let span = match &self.0 {
+            AnonExp::Literal(lit) => lit.span(),
+            AnonExp::Call(call) => call.span(),
+            AnonExp::Use(decl) => decl.span(),
+        };
+        match &self.1 {
+            Some(sym) => span.to(sym.span()),
+            None => span,
         }
     }
 
     pub fn call_args(&self) -> &[Exp<'a>] {
         match &self.0 {
-            AnonExp::Literal(_) => &[],
+            AnonExp::Literal(_) | AnonExp::Use(_) => &[],
             AnonExp::Call(Call { args, .. }) => args.as_slice(),
         }
     }
 }
 
+/// Skips whitespace and comments. Doc comments are left in place as they
+/// are parsed as a part of the following expression, unless no expression
+/// follows them.
+fn blank(input: CompleteStr) -> IResult<CompleteStr, CompleteStr> {
+    let rest = skip_blank(input.0, false);
+    let skipped = &input.0[..input.0.len() - rest.len()];
+    Ok((CompleteStr(rest), CompleteStr(skipped)))
+}
+
+/// Skips whitespace and comments, and doc comments too if `docs` is set.
+fn skip_blank(input: &str, docs: bool) -> &str {
+    let mut rest = input;
+    loop {
+        rest = rest.trim_left_matches(&[' ', '\t', '\r', '\n'][..]);
+        if rest.starts_with("##") && !docs {
+            // A doc comment at the end of a file or a list is a plain comment
+            let after = skip_blank(rest, true);
+            if after.is_empty() || after.starts_with(')') {
+                rest = after;
+            }
+            break;
+        } else if let Some(after) = block_comment(rest) {
+            rest = after;
+        } else if rest.starts_with('#') {
+            rest = &rest[rest.find('\n').unwrap_or(rest.len())..];
+        } else {
+            break;
+        }
+    }
+    rest
+}
+
+/// Skips a block comment, `#[ ... ]#`. Block comments nest. A `#[` that is
+/// never closed starts a line comment instead.
+fn block_comment(input: &str) -> Option<&str> {
+    if !input.starts_with("#[") {
+        return None;
+    }
+    let mut depth = 0;
+    let mut pos = 0;
+    while pos < input.len() {
+        if input[pos..].starts_with("#[") {
+            depth += 1;
+            pos += 2;
+        } else if input[pos..].starts_with("]#") {
+            depth -= 1;
+            pos += 2;
+            if depth == 0 {
+                return Some(&input[pos..]);
+            }
+        } else {
+            pos += input[pos..].chars().next().map(char::len_utf8).unwrap_or(1);
+        }
+    }
+    None
+}
+
+/// Like `ws!`, but skips comments too.
+macro_rules! wsc (
+    ($i:expr, $($args:tt)*) => ({
+        use nom::Convert;
+
+        match sep!($i, blank, $($args)*) {
+            Err(e) => Err(e),
+            Ok((i1, o)) => match blank(i1) {
+                Err(e) => Err(nom::Err::convert(e)),
+                Ok((i2, _)) => Ok((i2, o)),
+            },
+        }
+    })
+);
+
+#[test]
+fn test_blank() {
+    let result = blank(CompleteStr(" # comment\n\t#[ block #[ nested ]# ]#\n ## doc\nhoge"));
+
+    assert_eq!(result.unwrap().0, CompleteStr("## doc\nhoge"));
+
+    let result = blank(CompleteStr("#[deprecated]\n#[ block ]#\nhoge"));
+    assert_eq!(result.unwrap().0, CompleteStr("hoge"));
+
+    assert_eq!(blank(CompleteStr(" ## dangling\n")).unwrap().0, CompleteStr(""));
+    assert_eq!(blank(CompleteStr("## dangling\n)")).unwrap().0, CompleteStr(")"));
+}
+
 named!(symbol<CompleteStr, Sym>, do_parse!(
         sym: recognize!(tuple!(
             alt!(alpha1 | tag!("_")),
@@ -135,20 +338,130 @@ fn test_parse_symbol_4() {
     assert!(result.is_err());
 }
 
-named!(str_literal<CompleteStr, Lit>, do_parse!(
-        lit: delimited!(tag!("\""), is_not!("\""), tag!("\"")) >>
-        (Lit::Str(&lit))
-    ));
+fn escape_error(input: &str) -> nom::Err<CompleteStr> {
+    nom::Err::Failure(Context::Code(CompleteStr(input), ErrorKind::Escaped))
+}
+
+/// Parses the escape sequence after a backslash, returning the escaped
+/// character and the length of the sequence.
+fn escape_sequence(input: &str) -> Option<(char, usize)> {
+    let escaped = match input.chars().next()? {
+        't' => '\t',
+        'n' => '\n',
+        'r' => '\r',
+        '0' => '\0',
+        '"' => '"',
+        '\\' => '\\',
+        'u' => {
+            if !input[1..].starts_with('{') {
+                return None;
+            }
+            let end = input.find('}')?;
+            let hex = &input[2..end];
+            // `from_str_radix` would accept a sign too
+            if hex.is_empty() || hex.len() > 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return None;
+            }
+            let escaped = u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)?;
+            return Some((escaped, end + 1));
+        }
+        _ => return None,
+    };
+    Some((escaped, 1))
+}
+
+/// Parses a string literal. The value is borrowed from the source unless it
+/// contains escape sequences.
+fn str_literal(input: CompleteStr) -> IResult<CompleteStr, Lit> {
+    let input = input.0;
+    if !input.starts_with('"') {
+        return Err(nom::Err::Error(Context::Code(CompleteStr(input), ErrorKind::Tag)));
+    }
+
+    let mut unescaped: Option<String> = None;
+    let mut pos = 1;
+
+    while let Some(c) = input[pos..].chars().next() {
+        match c {
+            '"' => {
+                let value = match unescaped {
+                    Some(s) => Cow::Owned(s),
+                    None => Cow::Borrowed(&input[1..pos]),
+                };
+                let raw = &input[..pos + 1];
+                return Ok((CompleteStr(&input[pos + 1..]), Lit::Str(value, raw)));
+            }
+            '\\' => {
+                let (escaped, len) = match escape_sequence(&input[pos + 1..]) {
+                    Some(escape) => escape,
+                    None => return Err(escape_error(&input[pos..])),
+                };
+                unescaped
+                    .get_or_insert_with(|| input[1..pos].to_owned())
+                    .push(escaped);
+                pos += 1 + len;
+            }
+            c => {
+                if let Some(ref mut s) = unescaped {
+                    s.push(c);
+                }
+                pos += c.len_utf8();
+            }
+        }
+    }
+
+    // Unterminated string literal
+    Err(escape_error(input))
+}
 
 #[test]
 fn test_parse_str_literal() {
     let result = str_literal(CompleteStr("\"test\""));
 
-    assert_eq!(result, Ok((CompleteStr(""), Lit::Str("test"))));
+    assert_eq!(
+        result,
+        Ok((CompleteStr(""), Lit::Str(Cow::Borrowed("test"), "\"test\"")))
+    );
+}
+
+#[test]
+fn test_parse_str_literal_escapes() {
+    let result = str_literal(CompleteStr(r#""a\tb\"c\\d\u{3042}" rest"#));
+
+    assert_eq!(
+        result,
+        Ok((
+            CompleteStr(" rest"),
+            Lit::Str(
+                Cow::Owned("a\tb\"c\\d\u{3042}".to_owned()),
+                r#""a\tb\"c\\d\u{3042}""#
+            )
+        ))
+    );
+}
+
+#[test]
+fn test_parse_str_literal_empty() {
+    let result = str_literal(CompleteStr("\"\""));
+
+    assert_eq!(result, Ok((CompleteStr(""), Lit::Str(Cow::Borrowed(""), "\"\""))));
+}
+
+#[test]
+fn test_parse_str_literal_invalid() {
+    assert!(str_literal(CompleteStr(r#""\x""#)).is_err());
+    assert!(str_literal(CompleteStr(r#""\u{110000}""#)).is_err());
+    assert!(str_literal(CompleteStr(r#""\u{}""#)).is_err());
+    assert!(str_literal(CompleteStr(r#""\u{+41}""#)).is_err());
+    assert!(str_literal(CompleteStr(r#""unterminated"#)).is_err());
+
+    let source = r#"hoge("valid" "inva\lid")"#;
+    let err = parse_file(source).unwrap_err();
+    assert_eq!(err.0.span(), Some(Span::of(&source[18..19])));
 }
 
 named!(int_literal<CompleteStr, Lit>, do_parse!(
-        lit: digit1 >>
+        lit: recognize!(pair!(opt!(one_of!("+-")), digit1)) >>
         (Lit::Int(&lit))
     ));
 
@@ -157,10 +470,16 @@ fn test_parse_int_literal() {
     let result = int_literal(CompleteStr("3483"));
 
     assert_eq!(result, Ok((CompleteStr(""), Lit::Int("3483"))));
+    assert_eq!(int_literal(CompleteStr("-12")), Ok((CompleteStr(""), Lit::Int("-12"))));
+}
+
+/// Numbers without a fraction or an exponent are left to `int_literal`.
+fn is_float(lit: CompleteStr) -> bool {
+    lit.contains(|c| c == '.' || c == 'e' || c == 'E')
 }
 
 named!(float_literal<CompleteStr, Lit>, do_parse!(
-        lit: recognize_float >>
+        lit: verify!(recognize_float, is_float) >>
         (Lit::Float(&lit))
     ));
 
@@ -169,6 +488,14 @@ fn test_parse_float_literal() {
     let result = float_literal(CompleteStr("3483.4"));
 
     assert_eq!(result, Ok((CompleteStr(""), Lit::Float("3483.4"))));
+    assert!(float_literal(CompleteStr("3483")).is_err());
+}
+
+#[test]
+fn test_parse_literal() {
+    assert_eq!(literal(CompleteStr("3")), Ok((CompleteStr(""), Lit::Int("3"))));
+    assert_eq!(literal(CompleteStr("-1.5e3")), Ok((CompleteStr(""), Lit::Float("-1.5e3"))));
+    assert_eq!(literal(CompleteStr(".5")), Ok((CompleteStr(""), Lit::Float(".5"))));
 }
 
 named!(literal<CompleteStr, Lit>, do_parse!(
@@ -211,6 +538,7 @@ fn test_parse_par_list_1() {
                         args: vec![],
                     }),
                     None,
+                    None,
                 ),
                 Exp(
                     AnonExp::Call(Call {
@@ -218,6 +546,7 @@ fn test_parse_par_list_1() {
                         args: vec![],
                     }),
                     None,
+                    None,
                 ),
                 Exp(
                     AnonExp::Call(Call {
@@ -225,6 +554,7 @@ fn test_parse_par_list_1() {
                         args: vec![],
                     }),
                     None,
+                    None,
                 ),
             ]
         ))
@@ -245,6 +575,7 @@ fn test_parse_par_list_2() {
                     args: vec![],
                 }),
                 None,
+                None,
             )]
         ))
     );
@@ -257,7 +588,7 @@ fn test_parse_par_list_3() {
     assert_eq!(result, Ok((CompleteStr(""), vec![])));
 }
 
-named!(name_binding<CompleteStr, Sym>, ws!(preceded!(tag!(KEYWORD_AS), symbol)));
+named!(name_binding<CompleteStr, Sym>, wsc!(preceded!(tag!(KEYWORD_AS), symbol)));
 
 #[test]
 fn test_parse_name_binding() {
@@ -266,7 +597,7 @@ fn test_parse_name_binding() {
     assert_eq!(result, Ok((CompleteStr(""), Sym("piyo"))));
 }
 
-named!(anon_expression<CompleteStr, AnonExp>, ws!(alt!(do_parse!(
+named!(anon_expression<CompleteStr, AnonExp>, wsc!(alt!(do_parse!(
 		head: path >>
 		tail: opt!(par_list) >>
 		(AnonExp::Call(Call{ path: head, args: tail.unwrap_or(Vec::new())}))
@@ -275,12 +606,82 @@ named!(anon_expression<CompleteStr, AnonExp>, ws!(alt!(do_parse!(
 		(AnonExp::Literal(lit))
 	))));
 
-named!(named_expression<CompleteStr, Exp>, ws!(do_parse!(
-		exp: anon_expression >>
+named!(doc_line<CompleteStr, &str>, do_parse!(
+		tag!("##") >>
+		line: take_till!(|c| c == '\n') >>
+		(line.0)
+	));
+
+named!(doc<CompleteStr, Doc>, do_parse!(
+		lines: many1!(wsc!(doc_line)) >>
+		(Doc(lines))
+	));
+
+named!(use_name<CompleteStr, UseName>, wsc!(do_parse!(
+		name: symbol >>
 		bind: opt!(name_binding) >>
-		(Exp(exp, bind))
+		(UseName(name, bind))
+	)));
+
+named!(use_tree<CompleteStr, UseTree>, alt!(
+		map!(tag!("*"), |star| UseTree::Glob(Sym(star.0))) |
+		map!(delimited!(tag!("("), wsc!(many1!(use_name)), tag!(")")), UseTree::List)
+	));
+
+// Only a single imported item may be renamed with a trailing `as`
+named!(use_tail<CompleteStr, (UseTree, Option<Sym>)>, alt!(
+		do_parse!(tag!(".") >> tree: use_tree >> not!(name_binding) >> ((tree, None))) |
+		map!(opt!(name_binding), |bind| (UseTree::Single, bind))
+	));
+
+// The keyword must be followed by a blank to tell it from symbols like `user`
+named!(use_keyword<CompleteStr, CompleteStr>,
+	terminated!(tag!(KEYWORD_USE), peek!(one_of!(" \t\r\n#")))
+);
+
+named!(use_declaration<CompleteStr, Exp>, wsc!(do_parse!(
+		doc: opt!(doc) >>
+		keyword: use_keyword >>
+		head: path >>
+		tail: use_tail >>
+		(Exp(AnonExp::Use(Use{ keyword: Sym(keyword.0), path: head, tree: tail.0 }), tail.1, doc))
 	)));
 
+#[test]
+fn test_parse_use() {
+    let result = use_declaration(CompleteStr("use std.date.*"));
+    let decl = result.unwrap().1;
+    let decl = decl.use_decl().unwrap();
+    assert_eq!(decl.path, Path(vec![Sym("std"), Sym("date")]));
+    assert_eq!(decl.tree, UseTree::Glob(Sym("*")));
+
+    let result = use_declaration(CompleteStr("use std.date.(iso_hyphen japanese_slash as slash)"));
+    let decl = result.unwrap().1;
+    assert_eq!(
+        decl.use_decl().unwrap().tree,
+        UseTree::List(vec![
+            UseName(Sym("iso_hyphen"), None),
+            UseName(Sym("japanese_slash"), Some(Sym("slash"))),
+        ])
+    );
+
+    let result = use_declaration(CompleteStr("use std.date.iso_hyphen as date"));
+    let decl = result.unwrap().1;
+    assert_eq!(decl.bound_name(), Some("date"));
+    assert_eq!(decl.use_decl().unwrap().tree, UseTree::Single);
+
+    assert!(use_declaration(CompleteStr("user.name")).is_err());
+    // Only a single imported item may be renamed with a trailing `as`
+    assert!(parse_file("use std.date.* as date").is_err());
+}
+
+named!(named_expression<CompleteStr, Exp>, alt!(use_declaration | wsc!(do_parse!(
+		doc: opt!(doc) >>
+		exp: anon_expression >>
+		bind: opt!(name_binding) >>
+		(Exp(exp, bind, doc))
+	))));
+
 #[test]
 fn test_parse_exp_1() {
     let result = named_expression(CompleteStr("hoge(first second third) as fuga"));
@@ -299,6 +700,7 @@ fn test_parse_exp_1() {
                                 args: vec![],
                             }),
                             None,
+                            None,
                         ),
                         Exp(
                             AnonExp::Call(Call {
@@ -306,6 +708,7 @@ fn test_parse_exp_1() {
                                 args: vec![],
                             }),
                             None,
+                            None,
                         ),
                         Exp(
                             AnonExp::Call(Call {
@@ -313,10 +716,12 @@ fn test_parse_exp_1() {
                                 args: vec![],
                             }),
                             None,
+                            None,
                         ),
                     ],
                 }),
-                Some(Sym("fuga"))
+                Some(Sym("fuga")),
+                None,
             )
         ))
     );
@@ -335,7 +740,8 @@ fn test_parse_exp_2() {
                     path: Path(vec![Sym("hoge")]),
                     args: vec![],
                 }),
-                None
+                None,
+                None,
             )
         ))
     );
@@ -359,6 +765,7 @@ fn test_parse_exp_3() {
                                 args: vec![],
                             }),
                             None,
+                            None,
                         ),
                         Exp(
                             AnonExp::Call(Call {
@@ -366,6 +773,7 @@ fn test_parse_exp_3() {
                                 args: vec![],
                             }),
                             None,
+                            None,
                         ),
                         Exp(
                             AnonExp::Call(Call {
@@ -373,10 +781,12 @@ fn test_parse_exp_3() {
                                 args: vec![],
                             }),
                             None,
+                            None,
                         ),
                     ],
                 }),
-                None
+                None,
+                None,
             )
         ))
     );
@@ -395,7 +805,8 @@ fn test_parse_exp_4() {
                     path: Path(vec![Sym("hoge")]),
                     args: vec![],
                 }),
-                Some(Sym("fuga"))
+                Some(Sym("fuga")),
+                None,
             )
         ))
     );
@@ -414,7 +825,8 @@ fn test_parse_exp_5() {
                     path: Path(vec![Sym("or")]),
                     args: vec![],
                 }),
-                Some(Sym("day"))
+                Some(Sym("day")),
+                None,
             )
         ))
     );
@@ -431,15 +843,20 @@ fn test_parse_exp_6() {
             Exp(
                 AnonExp::Call(Call {
                     path: Path(vec![Sym("or")]),
-                    args: vec![Exp(AnonExp::Literal(Lit::Str("mon")), None)],
+                    args: vec![Exp(
+                        AnonExp::Literal(Lit::Str(Cow::Borrowed("mon"), "\"mon\"")),
+                        None,
+                        None,
+                    )],
                 }),
-                Some(Sym("day"))
+                Some(Sym("day")),
+                None,
             )
         ))
     );
 }
 
-named!(list<CompleteStr, Vec<Exp>>, ws!(many0!(named_expression)));
+named!(list<CompleteStr, Vec<Exp>>, wsc!(many0!(named_expression)));
 
 #[test]
 fn test_parse_list() {
@@ -456,6 +873,7 @@ fn test_parse_list() {
                         args: vec![],
                     }),
                     None,
+                    None,
                 ),
                 Exp(
                     AnonExp::Call(Call {
@@ -463,6 +881,39 @@ fn test_parse_list() {
                         args: vec![],
                     }),
                     None,
+                    None,
+                ),
+                Exp(
+                    AnonExp::Call(Call {
+                        path: Path(vec![Sym("piyo")]),
+                        args: vec![],
+                    }),
+                    None,
+                    None,
+                ),
+            ]
+        ))
+    );
+}
+
+#[test]
+fn test_parse_comments() {
+    let result = list(CompleteStr(
+        "# A comment\nhoge #[ block\n #[ nested ]# ]# as fuga\n## Documented\n##\n## More lines\npiyo",
+    ));
+
+    assert_eq!(
+        result,
+        Ok((
+            CompleteStr(""),
+            vec![
+                Exp(
+                    AnonExp::Call(Call {
+                        path: Path(vec![Sym("hoge")]),
+                        args: vec![],
+                    }),
+                    Some(Sym("fuga")),
+                    None,
                 ),
                 Exp(
                     AnonExp::Call(Call {
@@ -470,13 +921,41 @@ fn test_parse_list() {
                         args: vec![],
                     }),
                     None,
+                    Some(Doc(vec![" Documented", "", " More lines"])),
                 ),
             ]
         ))
     );
+
+    assert_eq!(
+        result.unwrap().1[1].doc().unwrap().text(),
+        "Documented\n\nMore lines"
+    );
+
+    // Doc comments that aren't followed by an expression are plain comments
+    let result = parse_file("hoge(fuga ## Dangling\n)\n## At the end\n").unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].call_args()[0].doc(), None);
 }
 
 pub fn parse_file(string: &str) -> Result<Vec<Exp>, SyntaxError> {
     let string = CompleteStr(string);
-    Ok(exact!(string, call!(list)).map_err(|_| SyntaxError)?.1)
+    match exact!(string, call!(list)) {
+        Ok((_, token_tree)) => Ok(token_tree),
+        Err(nom::Err::Error(nom::Context::Code(rest, _)))
+        | Err(nom::Err::Failure(nom::Context::Code(rest, _))) => {
+            // Points to the first character that couldn't be parsed
+            let len = rest.chars().next().map(char::len_utf8).unwrap_or(0);
+            Err(SyntaxError(Snippet::new(Span::of(&rest[..len]))))
+        }
+        Err(_) => Err(SyntaxError(Snippet::default())),
+    }
+}
+
+#[test]
+fn test_parse_file_error_span() {
+    let source = "hoge(fuga\npiyo) \"unterminated";
+    let err = parse_file(source).unwrap_err();
+
+    assert_eq!(err.0.span(), Some(Span::of(&source[16..17])));
 }
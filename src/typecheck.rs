@@ -1,90 +1,221 @@
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::mem;
 
-use failure::{Error, err_msg};
+use failure::Error;
+use kadouchi_abi::{Dimensions, ErrorBuf, IntrinsicFn, RawArg, ERROR_CAPACITY};
 use libloading::{self, Library};
+use regex::Regex;
 
-use errors::{WrongNumberOfArguments, WrongTypeOfArguments};
+use intrinsics::{Intrinsic, Registry};
+use errors::{
+    with_span, InvalidNumberError, MissingIntrinsicError, NativeIntrinsicError, WrongNumberOfArguments,
+    WrongTypeOfArguments,
+};
 use tokens::Lit;
 use nameres::{AbsPath2, Item};
+use span::Snippet;
+use validate::Value;
 use KEYWORD_INTRINSIC;
 
-#[repr(C)]
-#[derive(Clone, Debug)]
-pub struct Dimensions {
-    x: u16,
-    y: u16,
-}
-
-type InitFuncPtr = extern "C" fn(&mut ExternObject, u16, *const &ObjectKind) -> bool;
-
 #[derive(Clone, Debug)]
 pub struct Object<'str> {
     inner: ObjectKind<'str>,
     args: Vec<Object<'str>>,
 }
 
-#[repr(u8)]
 #[derive(Clone, Debug)]
 pub enum ObjectKind<'str> {
     Extern(ExternObject),
     StrLit(&'str str),
+    Int(i64),
+    Float(f64),
     Caller,
     Empty,
 }
 
-#[repr(C)]
+/// The implementation of an intrinsic: either a built-in one or a named
+/// function of a native library.
+#[derive(Clone)]
+pub enum Init {
+    Builtin(&'static Intrinsic),
+    Native(String, IntrinsicFn),
+}
+
+/// What an object can be used for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Class {
+    /// A cell type, usable in a `row` and in `or` and `and`
+    Cell,
+    /// A `row` or a `col`
+    Layout,
+    /// A setting of a `dialect`
+    Setting,
+    Dialect,
+    /// A top-level layout with a dialect
+    File,
+    /// Anything else, like modules
+    Other,
+}
+
 #[derive(Clone)]
 pub struct ExternObject {
-    init: InitFuncPtr,
-    dimensions: (i16, i16),
+    pub init: Init,
+    pub class: Class,
+    pub dimensions: (i16, i16),
+    /// The name the object is bound to, for error messages
+    pub name: Option<String>,
+    /// The compiled pattern of a `regexp`
+    pub pattern: Option<Regex>,
+}
+
+impl ExternObject {
+    pub fn builtin(intrinsic: &'static Intrinsic) -> Self {
+        let mut object = ExternObject {
+            init: Init::Builtin(intrinsic),
+            class: Class::Other,
+            dimensions: (0, 0),
+            name: None,
+            pattern: None,
+        };
+        intrinsic.init_bare(&mut object);
+        object
+    }
+
+    pub fn native(name: &str, func: IntrinsicFn) -> Self {
+        ExternObject {
+            init: Init::Native(name.to_owned(), func),
+            class: Class::Other,
+            dimensions: (0, 0),
+            name: None,
+            pattern: None,
+        }
+    }
+
+    /// Initializes the object by calling its intrinsic with the arguments.
+    pub fn call(&mut self, args: &[&ObjectKind]) -> Result<(), Error> {
+        match self.init {
+            Init::Builtin(intrinsic) => intrinsic.init(self, args),
+            Init::Native(ref name, func) => {
+                let raw_args = args.iter().map(|arg| raw_arg(arg)).collect::<Vec<_>>();
+                let (width, height) = self.dimensions;
+                let mut dimensions = Dimensions::new(width, height);
+                let mut buffer = [0; ERROR_CAPACITY];
+
+                trace!("Call native function {}", name);
+                unsafe {
+                    let mut error = ErrorBuf::new(&mut buffer);
+                    if !func(&mut dimensions, raw_args.as_ptr(), raw_args.len(), &mut error) {
+                        let message = error.message().to_owned();
+                        return Err(
+                            NativeIntrinsicError(name.clone(), message, Snippet::default()).into(),
+                        );
+                    }
+                }
+                trace!("Native function {} finished.", name);
+
+                // Native objects only have dimensions; the 1×1 ones are cells
+                self.dimensions = (dimensions.width, dimensions.height);
+                self.class = match self.dimensions {
+                    (0, 0) => Class::Other,
+                    (1, 1) => Class::Cell,
+                    _ => Class::Layout,
+                };
+                Ok(())
+            }
+        }
+    }
+
+    /// The runtime value of the object after calling it with the arguments.
+    pub fn value<'a>(
+        &self,
+        args: Vec<Value<'a>>,
+        names: Vec<Option<&'a str>>,
+    ) -> Result<Value<'a>, Error> {
+        match self.init {
+            Init::Builtin(intrinsic) => intrinsic.value(self, args, names),
+            Init::Native(ref name, _) => Ok(native_value(name, self.dimensions)),
+        }
+    }
+
+    /// The runtime value of the object when it refers to its intrinsic without
+    /// calling it.
+    pub fn bare_value<'a>(&self) -> Value<'a> {
+        match self.init {
+            Init::Builtin(intrinsic) => intrinsic.bare_value(),
+            Init::Native(ref name, _) => native_value(name, self.dimensions),
+        }
+    }
 }
 
+/// The runtime value of a native object. Only the shapes of native objects are
+/// known, so they're built of cells that match anything: the wider objects are
+/// rows of them and the higher ones cols of those rows.
+fn native_value<'a>(name: &str, (width, height): (i16, i16)) -> Value<'a> {
+    let cell = || Value::Native(name.to_owned());
+    let row = || Value::Row((0..width).map(|_| cell()).collect(), vec![None; width as usize]);
+    match (width, height) {
+        (0, 0) | (1, 1) => cell(),
+        (_, 1) => row(),
+        _ => Value::Col((0..height).map(|_| row()).collect(), vec![None; height as usize]),
+    }
+}
 
 impl fmt::Debug for ExternObject {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        self.class.fmt(formatter)?;
         self.dimensions.fmt(formatter)?;
-        (self.init as usize).fmt(formatter)?;
+        match self.init {
+            Init::Builtin(intrinsic) => intrinsic.name().fmt(formatter),
+            Init::Native(ref name, _) => name.fmt(formatter),
+        }
+    }
+}
 
-        Ok(())
+/// Converts an argument to its representation in the native ABI. Only the
+/// dimensions of objects are visible to native intrinsics.
+fn raw_arg(arg: &ObjectKind) -> RawArg {
+    match arg {
+        ObjectKind::StrLit(string) => RawArg::string(string),
+        ObjectKind::Int(int) => RawArg::int(*int),
+        ObjectKind::Float(float) => RawArg::float(*float),
+        ObjectKind::Extern(ex) => RawArg::object(Dimensions::new(ex.dimensions.0, ex.dimensions.1)),
+        ObjectKind::Caller | ObjectKind::Empty => RawArg::object(Dimensions::default()),
     }
 }
 
+/// Looks up the intrinsic named by the argument of `intrinsic`, first from the
+/// built-ins and then from the native library.
 fn emit_extern_object(
-    ex: &mut ExternObject,
-    natives: &Library,
+    registry: &Registry,
+    natives: Option<&Library>,
     args: &[Object],
-) -> Result<(), Error> {
-    if args.len() == 0 {
-        return Ok(());
-    }
-    if args.len() > 1 {
-        return Err(WrongNumberOfArguments(1, args.len()).into());
+) -> Result<ExternObject, Error> {
+    if args.len() != 1 {
+        return Err(WrongNumberOfArguments(1, args.len(), Snippet::default()).into());
     }
 
     let call = match args[0].inner {
         ObjectKind::StrLit(call) => call,
-        _ => return Err(WrongTypeOfArguments.into()),
+        _ => return Err(WrongTypeOfArguments(Snippet::default()).into()),
     };
 
-    let arg_count = args.len() as u16;
-    let arg_inners = args.iter()
-                .map(|a| &a.inner)
-                .collect::<Vec<&ObjectKind>>();
-
-    trace!("Call function {}", call);
-    let result = unsafe {
-        let func: libloading::Symbol<InitFuncPtr> = natives.get(call.as_bytes())?;
-        func(ex, arg_count, arg_inners.as_slice().as_ptr())
-    };
-    trace!("Function {} finished with result {}", call, result);
+    if let Some(intrinsic) = registry.get(call) {
+        trace!("Found the built-in intrinsic {}", call);
+        return Ok(ExternObject::builtin(intrinsic));
+    }
 
-    return Ok(());
-}
+    let missing = || MissingIntrinsicError(call.to_owned(), Snippet::default());
+    let natives = natives.ok_or_else(missing)?;
 
+    trace!("Found the native intrinsic {}", call);
+    let func = unsafe {
+        let func: libloading::Symbol<IntrinsicFn> =
+            natives.get(call.as_bytes()).map_err(|_| missing())?;
+        *func
+    };
 
-#[no_mangle]
-pub extern "C" fn noop(_: &mut ExternObject, _: u16, _: *const &ObjectKind) -> bool {
-    true
+    Ok(ExternObject::native(call, func))
 }
 
 impl<'str> Object<'str> {
@@ -96,14 +227,20 @@ impl<'str> Object<'str> {
         }
     }
 
-    fn build_string_literal(string: &'str str) -> Object<'str> {
-        Object {
-            inner: ObjectKind::StrLit(string),
+    fn build_literal(literal: &'str Lit<'str>) -> Result<Object<'str>, Error> {
+        let invalid = |raw: &str| InvalidNumberError(raw.to_owned(), Snippet::default());
+        let inner = match literal {
+            Lit::Str(string, _) => ObjectKind::StrLit(string),
+            Lit::Int(raw) => ObjectKind::Int(raw.parse().map_err(|_| invalid(raw))?),
+            Lit::Float(raw) => ObjectKind::Float(raw.parse().map_err(|_| invalid(raw))?),
+        };
+        Ok(Object {
+            inner,
             args: Vec::new(),
-        }
+        })
     }
 
-    fn build_extern_caller_object(natives: &'str Library) -> Object<'str> {
+    fn build_extern_caller_object() -> Object<'str> {
         Object {
             inner: ObjectKind::Caller,
             args: Vec::new(),
@@ -123,32 +260,27 @@ impl<'str> Object<'str> {
         }
     }
 
-    fn check(&mut self, natives: &Library) -> Result<(), Error> {
+    fn check(&mut self, registry: &Registry, natives: Option<&Library>) -> Result<(), Error> {
         match self.inner {
             ObjectKind::Extern(ref mut ex) => {
                 trace!("check ObjectKind::Extern");
-                let arg_count = self.args.len() as u16;
-                let arg_inners = self.args.iter()
-                    .map(|a| &a.inner)
-                    .collect::<Vec<&ObjectKind>>()
-                    .as_ptr();
-
-                trace!("Call function {:x}", ex.init as usize);
-                if !(ex.init)(ex, arg_count, arg_inners) {
-                    return Err(err_msg("Extern function returned error"));
+                // Referring to an object without arguments is just an alias
+                if !self.args.is_empty() {
+                    let arg_inners = self.args.iter().map(|a| &a.inner).collect::<Vec<_>>();
+                    ex.call(&arg_inners)?;
                 }
-                trace!("Function call {:x} finished.", ex.init as usize);
             },
             ObjectKind::Caller => {
                 trace!("check ObjectKind::Caller");
-                let mut ex = ExternObject {
-                    init: noop,
-                    dimensions: (0, 0),
-                };
-                emit_extern_object(&mut ex, natives, &self.args)?;
+                let ex = emit_extern_object(registry, natives, &self.args)?;
                 self.inner = ObjectKind::Extern(ex);
             },
-            _ => panic!("No other kinds of objects except Externs and Callers need checking!"),
+            // Literals can be aliased but not called
+            _ => {
+                if !self.args.is_empty() {
+                    return Err(WrongNumberOfArguments(0, self.args.len(), Snippet::default()).into());
+                }
+            },
         }
         Ok(())
     }
@@ -166,81 +298,189 @@ fn retrieve_object<'a, 'str: 'a>(path: &AbsPath2, root: &'a mut Object<'str>) ->
     Ok(obj)
 }
 
-pub fn check_recursive<'a, 'str: 'a>(
-    natives: &Library,
-    item: &Item<'str>,
-    root: &'a mut Object<'str>,
-    current_path: &mut AbsPath2,
-) -> Result<(), Error> {
-    // Checking the arguments first
-    for (item_idx, arg) in item.ns.items.iter().enumerate() {
-        if let Some(ref referent) = arg.referent {
-            trace!("Starting to create object {:?} (object own path {:?} with parent path {:?}), which is based to object {:?}", arg.local_name, arg.path, current_path, referent);
-
-            let obj = retrieve_object(referent, root)?;
+/// Builds the objects of an item and its arguments before they are checked.
+/// Literals are complete as they are, the rest are filled in by `check_item`.
+fn build_objects<'str>(item: &'str Item<'str>) -> Result<Object<'str>, Error> {
+    if let Some(ref literal) = item.literal {
+        trace!("Creating a literal object.");
+        return Object::build_literal(literal).map_err(|err| match item.span {
+            Some(span) => with_span(err, span),
+            None => err,
+        });
+    }
 
-            let new_obj = obj.init();
+    let mut obj = if item.local_name == Some(KEYWORD_INTRINSIC) && item.path.iter_segments().count() == 1 {
+        trace!("Intrisic object was created.");
+        Object::build_extern_caller_object()
+    } else {
+        Object::build_empty_object()
+    };
+    for arg in &item.ns.items {
+        obj.add_arg(build_objects(arg)?);
+    }
+    Ok(obj)
+}
 
-            let parent = retrieve_object(current_path, root)?;
-            let obj_idx = parent.add_arg(new_obj);
-            debug_assert_eq!(item_idx, obj_idx);
+/// Checks an item after its arguments and its referent, which may be defined
+/// later in the library. The name resolution has ruled out reference cycles.
+/// The runtime values of the checked items are collected into `values`.
+fn check_item<'str, 'a: 'str>(
+    registry: &Registry,
+    natives: Option<&Library>,
+    root_item: &'str Item<'a>,
+    item: &'str Item<'a>,
+    root: &mut Object<'str>,
+    checked: &mut HashSet<&'str AbsPath2>,
+    values: &mut HashMap<AbsPath2, Value<'a>>,
+) -> Result<(), Error> {
+    if !checked.insert(&item.path) {
+        return Ok(());
+    }
 
-            current_path.push_segment(item_idx);
-            check_recursive(natives, arg, root, current_path)?;
-            let arg_object = retrieve_object(current_path, root)?;
-            current_path.pop_segment();
+    for arg in &item.ns.items {
+        check_item(registry, natives, root_item, arg, root, checked, values)?;
+    }
 
-            trace!(
-                "After initializing its arguments, we are checking the object {:?} itself:\n{:?}",
-                arg.local_name,
-                arg_object
-            );
+    if let Some(ref literal) = item.literal {
+        let value = match (literal, &retrieve_object(&item.path, root)?.inner) {
+            (Lit::Str(string, _), _) => Value::Str(string.clone()),
+            (_, ObjectKind::Int(int)) => Value::Int(*int),
+            (_, ObjectKind::Float(float)) => Value::Float(*float),
+            _ => panic!("Invariant: number literals are built into number objects."),
+        };
+        values.insert(item.path.clone(), value);
+    }
 
-            arg_object.check(natives)?;
+    if let Some(ref referent) = item.referent {
+        trace!("Starting to create object {:?} (object own path {:?}), which is based to object {:?}", item.local_name, item.path, referent);
+
+        let referent_item = root_item.traverse_path(referent);
+        check_item(registry, natives, root_item, referent_item, root, checked, values)?;
+        let referent_obj = retrieve_object(referent, root)?;
+        let is_caller = match referent_obj.inner {
+            ObjectKind::Caller => true,
+            _ => false,
+        };
+        let new_obj = referent_obj.init();
+
+        let obj = retrieve_object(&item.path, root)?;
+        let args = mem::replace(&mut obj.args, Vec::new());
+        *obj = new_obj;
+        obj.args = args;
+
+        trace!(
+            "After initializing its arguments, we are checking the object {:?} itself:\n{:?}",
+            item.local_name,
+            obj
+        );
+
+        obj.check(registry, natives).map_err(|err| match item.span {
+            Some(span) => with_span(err, span),
+            None => err,
+        })?;
+        if let ObjectKind::Extern(ref mut ex) = obj.inner {
+            ex.name = item.local_name.map(str::to_owned);
         }
-        if let Some(Lit::Str(ref literal)) = arg.literal {
-            trace!("Creating a literal object.");
-
-            current_path.push_segment(item_idx);
-            let new_obj = Object::build_string_literal(literal);
-            current_path.pop_segment();
 
-            let parent = retrieve_object(current_path, root)?;
-            let obj_idx = parent.add_arg(new_obj);
+        let value = match obj.inner {
+            ObjectKind::Extern(ref ex) if is_caller => Some(ex.bare_value()),
+            // Referring to an object without arguments is just an alias
+            _ if item.ns.items.is_empty() => values.get(referent).cloned(),
+            ObjectKind::Extern(ref ex) => {
+                let args = item
+                    .ns
+                    .items
+                    .iter()
+                    .map(|arg| values.get(&arg.path).cloned())
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| WrongTypeOfArguments(Snippet::default()).into());
+                let names = item.ns.items.iter().map(|arg| arg.local_name).collect();
+                let value = args.and_then(|args| ex.value(args, names));
+                Some(value.map_err(|err| match item.span {
+                    Some(span) => with_span(err, span),
+                    None => err,
+                })?)
+            }
+            _ => None,
+        };
+        if let Some(value) = value {
+            values.insert(item.path.clone(), value);
         }
     }
 
     Ok(())
 }
 
-pub fn check(root_item: &Item) -> Result<(), Error> {
+/// Typechecks the libraries of the tree and builds the runtime values of their
+/// items, keyed by the item paths. The intrinsics used by a library are either
+/// built-ins of `registry` or provided by its native companion in `natives`,
+/// keyed by the library name.
+pub fn check<'a>(
+    root_item: &Item<'a>,
+    registry: &Registry,
+    natives: &HashMap<&str, Library>,
+) -> Result<HashMap<AbsPath2, Value<'a>>, Error> {
     info!("Typecheck starts.");
 
-    let lib = libloading::Library::new("src/stdlib/libstd.dylib")?; // FIXME generalize this to any library
+    let mut root_obj = build_objects(root_item)?;
+    let mut checked = HashSet::new();
+    let mut values = HashMap::new();
+
+    // The libraries only refer to the ones before them, which are checked first
+    for lib in &root_item.ns.items {
+        trace!("Typecheck. Item: {:#?}", lib);
+
+        if let Some(local_name) = lib.local_name {
+            if local_name != KEYWORD_INTRINSIC {
+                values.insert(lib.path.clone(), Value::Module);
+            }
+            for item in &lib.ns.items {
+                check_item(
+                    registry,
+                    natives.get(local_name),
+                    root_item,
+                    item,
+                    &mut root_obj,
+                    &mut checked,
+                    &mut values,
+                )?;
+            }
 
-    let mut root_obj = Object::build_empty_object();
-    let mut current_path = AbsPath2::new(vec![]);
+            trace!("Inited {} successfully.", local_name);
+        }
+    }
 
-    for (idx, i) in root_item.ns.items.iter().enumerate() {
-        trace!("Typecheck. Item: {:#?}", i);
+    Ok(values)
+}
 
-        if let Some(local_name) = i.local_name {
-            current_path.push_segment(idx);
-            let obj = if local_name == KEYWORD_INTRINSIC {
-                trace!("Intrisic object was created.");
-                Object::build_extern_caller_object(&lib)
-            } else {
-                trace!("Library object {:?} was created.", local_name);
-                Object::build_empty_object()
-            };
+#[test]
+fn test_native_intrinsic() {
+    use kadouchi_abi::Arg;
+
+    intrinsic!(fn pair(args: &[Arg]) -> Result<Dimensions, String> {
+        let strings = args.iter().filter(|arg| match arg {
+            Arg::Str(_) => true,
+            _ => false,
+        });
+        if args.len() == 2 && strings.count() == 2 {
+            Ok(Dimensions::new(2, 1))
+        } else {
+            Err("pair expects two strings".to_owned())
+        }
+    });
 
-            root_obj.add_arg(obj);
-            check_recursive(&lib, i, &mut root_obj, &mut current_path)?;
-            current_path.pop_segment();
+    let mut object = ExternObject::native("pair", pair);
+    let cell = ObjectKind::StrLit("cell");
 
-            trace!("Inited {} successfully.", local_name);
-        }
-    }
+    object.call(&[&cell, &cell]).unwrap();
+    assert_eq!(object.dimensions, (2, 1));
 
-    Ok(())
+    let value = object.value(Vec::new(), Vec::new()).unwrap();
+    assert_eq!(value.to_string(), "row(pair pair)");
+
+    let err = object.call(&[&cell]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "The native intrinsic pair failed: pair expects two strings"
+    );
 }
@@ -0,0 +1,1199 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::mem;
+use std::path::Path;
+
+use failure::Error;
+use regex::Regex;
+
+use data::{CellType, Data};
+use datetime::DateFormat;
+use dialect::{Dialect, Record, RecordReader, Setting};
+use errors::{InvalidDataError, NoLayoutError, TooManyMismatchesError, WrongTypeOfArguments};
+use intrinsics::Repetition;
+use nameres::Item;
+use span::Snippet;
+use Schema;
+
+/// The runtime value of an item: either a cell type, a layout or something
+/// that isn't used for matching data, like a module.
+#[derive(Clone, Debug)]
+pub enum Value<'a> {
+    Intrinsic(&'static str),
+    Str(Cow<'a, str>),
+    Int(i64),
+    Float(f64),
+    Regexp(Cow<'a, str>, Regex),
+    Or(Vec<Value<'a>>),
+    And(Vec<Value<'a>>),
+    /// A numeric cell type like `int(0 100)`, checked by converting the cells
+    Number(CellType),
+    /// Dates and times in a format like `format("%Y-%m-%d")`
+    Format(Cow<'a, str>, DateFormat),
+    /// The cells of a row and the names they're bound to with `as`
+    Row(Vec<Value<'a>>, Vec<Option<&'a str>>),
+    /// The children of a col and the names they're bound to with `as`
+    Col(Vec<Value<'a>>, Vec<Option<&'a str>>),
+    Repeat(Repetition, Box<Value<'a>>),
+    Setting(Setting),
+    Dialect(Dialect),
+    /// A layout with the dialect its data files are written in
+    File(Dialect, Box<Value<'a>>),
+    /// A cell of a native intrinsic. Native intrinsics only describe the
+    /// shapes of their objects, so their cells match anything.
+    Native(String),
+    Module,
+    Export,
+}
+
+impl<'a> Value<'a> {
+    fn is_layout(&self) -> bool {
+        match self {
+            Value::Row(..) | Value::Col(..) | Value::Repeat(..) => true,
+            _ => false,
+        }
+    }
+
+    pub fn matches(&self, cell: &str) -> bool {
+        match self {
+            Value::Str(s) => *s == cell,
+            Value::Regexp(_, re) => re.is_match(cell),
+            Value::Number(cell_type) => cell_type.convert(cell).is_some(),
+            Value::Format(_, format) => format.convert(cell).is_some(),
+            Value::Or(alternatives) => alternatives.iter().any(|a| a.matches(cell)),
+            Value::And(constituents) => constituents.iter().all(|c| c.matches(cell)),
+            Value::Native(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Converts a cell that matches this cell type into typed data. Literals
+    /// become tags, and an `or` converts the cell like the first alternative
+    /// that matches it.
+    pub fn convert(&self, cell: &str) -> Option<Data> {
+        match self {
+            Value::Str(s) if *s == cell => Some(Data::Tag(cell.to_owned())),
+            Value::Regexp(_, re) if re.is_match(cell) => Some(Data::Str(cell.to_owned())),
+            Value::Number(cell_type) => cell_type.convert(cell),
+            Value::Format(_, format) => format.convert(cell),
+            Value::Native(_) => Some(Data::Str(cell.to_owned())),
+            Value::Or(alternatives) => alternatives.iter().filter_map(|a| a.convert(cell)).next(),
+            Value::And(constituents) if self.matches(cell) => {
+                // A typed conversion wins over the plain string of a regexp
+                let conversions = constituents
+                    .iter()
+                    .filter_map(|c| c.convert(cell))
+                    .collect::<Vec<_>>();
+                let typed = conversions.iter().position(|data| match data {
+                    Data::Str(_) => false,
+                    _ => true,
+                });
+                conversions.into_iter().nth(typed.unwrap_or(0))
+            }
+            _ => None,
+        }
+    }
+
+    /// Why a cell doesn't match this cell type, if it's written like its
+    /// numbers but violates the constraints.
+    pub fn explain_mismatch(&self, cell: &str) -> Option<String> {
+        match self {
+            Value::Number(cell_type) => cell_type.explain(cell),
+            Value::Format(_, format) => format.explain(cell),
+            Value::And(constituents) => constituents
+                .iter()
+                .filter_map(|c| c.explain_mismatch(cell))
+                .next(),
+            _ => None,
+        }
+    }
+
+    /// The string literal alternative of an `or` that is closest to a cell
+    /// that doesn't match it, by edit distance.
+    pub fn closest_alternative(&self, cell: &str) -> Option<&str> {
+        let mut literals = Vec::new();
+        self.literal_alternatives(&mut literals);
+        literals
+            .into_iter()
+            .min_by_key(|literal| edit_distance(literal, cell))
+    }
+
+    fn literal_alternatives<'v>(&'v self, literals: &mut Vec<&'v str>) {
+        if let Value::Or(alternatives) = self {
+            for alternative in alternatives {
+                match alternative {
+                    Value::Str(literal) => literals.push(literal),
+                    Value::Or(_) => alternative.literal_alternatives(literals),
+                    _ => (),
+                }
+            }
+        }
+    }
+}
+
+/// The Levenshtein distance between two strings, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..b.len() + 1).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + if a_char == *b_char { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous.copy_from_slice(&current);
+    }
+
+    previous[b.len()]
+}
+
+#[test]
+fn test_edit_distance() {
+    assert_eq!(edit_distance("", ""), 0);
+    assert_eq!(edit_distance("tues", "tue"), 1);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("päivä", "paiva"), 2);
+}
+
+fn fmt_list(name: &str, values: &[Value], formatter: &mut fmt::Formatter) -> fmt::Result {
+    formatter.write_str(name)?;
+    formatter.write_str("(")?;
+    for (idx, value) in values.iter().enumerate() {
+        if idx > 0 {
+            formatter.write_str(" ")?;
+        }
+        write!(formatter, "{}", value)?;
+    }
+    formatter.write_str(")")
+}
+
+impl<'a> fmt::Display for Value<'a> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Intrinsic(name) => formatter.write_str(name),
+            Value::Str(s) => write!(formatter, "{:?}", s),
+            Value::Int(int) => write!(formatter, "{}", int),
+            Value::Float(float) => write!(formatter, "{:?}", float),
+            Value::Regexp(pattern, _) => write!(formatter, "regexp({:?})", pattern),
+            Value::Or(alternatives) => fmt_list("or", alternatives, formatter),
+            Value::And(constituents) => fmt_list("and", constituents, formatter),
+            Value::Number(cell_type) => write!(formatter, "{}", cell_type),
+            Value::Format(pattern, _) => write!(formatter, "format({:?})", pattern),
+            Value::Row(cells, _) => fmt_list("row", cells, formatter),
+            Value::Col(children, _) => fmt_list("col", children, formatter),
+            Value::Repeat(repetition, layout) => match repetition.name() {
+                "repeat" => write!(formatter, "repeat(\"{}\" {})", repetition, layout),
+                name => write!(formatter, "{}({})", name, layout),
+            },
+            Value::Setting(setting) => write!(formatter, "{}", setting),
+            Value::Dialect(dialect) => write!(formatter, "{}", dialect),
+            Value::File(dialect, layout) => write!(formatter, "file({} {})", dialect, layout),
+            Value::Native(name) => formatter.write_str(name),
+            Value::Module => formatter.write_str("module"),
+            Value::Export => formatter.write_str("export"),
+        }
+    }
+}
+
+/// Finds the layout that describes the data files of the user library, which is
+/// the last library of the tree. The layout is the exported item named after the
+/// library.
+pub fn main_layout<'a>(root: &'a Item<'a>) -> Result<&'a Item<'a>, Error> {
+    let lib = root
+        .ns
+        .items
+        .last()
+        .expect("Invariant: the root contains at least the user library.");
+    let libname = lib.local_name.unwrap_or("(anon)");
+
+    match lib.ns.item(libname) {
+        Some(layout) if layout.exported => Ok(layout),
+        _ => Err(NoLayoutError(libname.to_owned()).into()),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CellReport {
+    pub line: usize,
+    pub column: usize,
+    pub content: String,
+    pub expected: String,
+    pub matches: bool,
+    /// The alternative that came closest, if the cell didn't match an `or`.
+    pub closest: Option<String>,
+    /// Why the cell didn't match, if its cell type can tell.
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mismatch {
+    Width { expected: usize, found: usize },
+    MissingLine,
+    UnexpectedLine,
+    /// A quoted cell isn't terminated or is followed by something else than a
+    /// delimiter
+    Quoting,
+    LineEnding,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineReport {
+    pub line: usize,
+    pub mismatch: Mismatch,
+}
+
+impl fmt::Display for LineReport {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.mismatch {
+            Mismatch::Width { expected, found } => write!(
+                formatter,
+                "line {}: expected {} cells but found {}",
+                self.line, expected, found
+            ),
+            Mismatch::MissingLine => write!(formatter, "line {}: expected more lines", self.line),
+            Mismatch::UnexpectedLine => {
+                write!(formatter, "line {}: line not described by the layout", self.line)
+            }
+            Mismatch::Quoting => write!(formatter, "line {}: invalid quoting", self.line),
+            Mismatch::LineEnding => {
+                write!(formatter, "line {}: line ending not allowed by the dialect", self.line)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    pub cells: Vec<CellReport>,
+    pub lines: Vec<LineReport>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.lines.is_empty() && self.cells.iter().all(|c| c.matches)
+    }
+
+    pub fn mismatched_cells<'r>(&'r self) -> impl Iterator<Item = &'r CellReport> + 'r {
+        self.cells.iter().filter(|c| !c.matches)
+    }
+}
+
+/// A `col` with a single `row` describes any number of lines of that row.
+pub(crate) fn is_repeated(children: &[Value]) -> bool {
+    match children.first() {
+        Some(Value::Row(..)) => children.len() == 1,
+        _ => false,
+    }
+}
+
+fn min_lines(layout: &Value) -> usize {
+    match layout {
+        Value::Col(ref children, _) if is_repeated(children) => 0,
+        Value::Col(children, _) => children.iter().map(min_lines).sum(),
+        Value::Repeat(repetition, layout) => repetition.min * min_lines(layout),
+        _ => 1,
+    }
+}
+
+/// Whether a layout always takes `min_lines` lines.
+fn is_fixed(layout: &Value) -> bool {
+    match layout {
+        Value::Col(ref children, _) if is_repeated(children) => false,
+        Value::Col(children, _) => children.iter().all(is_fixed),
+        Value::Repeat(repetition, layout) => repetition.max == Some(repetition.min) && is_fixed(layout),
+        _ => true,
+    }
+}
+
+/// The literal cells of a header row: an unnamed `row` of string literals. The
+/// cells name the cells of the layout that follows the header row.
+pub fn header<'v>(layout: &'v Value, name: Option<&str>) -> Option<Vec<&'v str>> {
+    match (layout, name) {
+        (Value::Row(cells, _), None) => cells
+            .iter()
+            .map(|cell| match cell {
+                Value::Str(literal) => Some(&**literal),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+/// Receives the results of a validation as the data is read.
+pub trait Visitor {
+    /// The cells of a line that was matched against a `row`.
+    fn visit_row(&mut self, cells: Vec<CellReport>) -> Result<(), Error>;
+
+    /// A line that doesn't fit the layout or the dialect.
+    fn visit_line(&mut self, line: LineReport) -> Result<(), Error>;
+
+    /// The data of a line that was matched against a `row`, when the data is
+    /// parsed one record at a time. The cells without a name are named by
+    /// `columns`, the cells of the header row that the layout follows.
+    fn visit_record(&mut self, _record: Data, _columns: Option<&[&str]>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Visitor for Report {
+    fn visit_row(&mut self, cells: Vec<CellReport>) -> Result<(), Error> {
+        self.cells.extend(cells);
+        Ok(())
+    }
+
+    fn visit_line(&mut self, line: LineReport) -> Result<(), Error> {
+        self.lines.push(line);
+        Ok(())
+    }
+}
+
+/// Passes the results on to another visitor, and stops the validation with
+/// `TooManyMismatchesError` once it has passed on `limit` mismatches. A limit
+/// of 0 means no limit.
+pub struct ErrorLimit<'v> {
+    visitor: &'v mut Visitor,
+    limit: usize,
+    mismatches: usize,
+}
+
+impl<'v> ErrorLimit<'v> {
+    pub fn new(visitor: &'v mut Visitor, limit: usize) -> Self {
+        ErrorLimit {
+            visitor,
+            limit,
+            mismatches: 0,
+        }
+    }
+
+    fn count(&mut self, mismatches: usize) -> Result<(), Error> {
+        self.mismatches += mismatches;
+        if self.limit > 0 && self.mismatches >= self.limit {
+            Err(TooManyMismatchesError(self.limit).into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'v> Visitor for ErrorLimit<'v> {
+    fn visit_row(&mut self, cells: Vec<CellReport>) -> Result<(), Error> {
+        let mismatches = cells.iter().filter(|c| !c.matches).count();
+        self.visitor.visit_row(cells)?;
+        self.count(mismatches)
+    }
+
+    fn visit_line(&mut self, line: LineReport) -> Result<(), Error> {
+        self.visitor.visit_line(line)?;
+        self.count(1)
+    }
+
+    fn visit_record(&mut self, record: Data, columns: Option<&[&str]>) -> Result<(), Error> {
+        self.visitor.visit_record(record, columns)
+    }
+}
+
+/// The results of an attempted repetition, passed on if the repetition is
+/// taken.
+struct Attempt {
+    rows: Vec<Vec<CellReport>>,
+    records: Vec<(Data, Option<Vec<String>>)>,
+    /// Whether the lines have the shape of the layout
+    fits: bool,
+    /// Whether the cells of the lines match too
+    matches: bool,
+}
+
+impl Attempt {
+    fn new() -> Self {
+        Attempt {
+            rows: Vec::new(),
+            records: Vec::new(),
+            fits: true,
+            matches: true,
+        }
+    }
+}
+
+impl Visitor for Attempt {
+    fn visit_row(&mut self, cells: Vec<CellReport>) -> Result<(), Error> {
+        self.matches &= cells.iter().all(|c| c.matches);
+        self.rows.push(cells);
+        Ok(())
+    }
+
+    fn visit_line(&mut self, _line: LineReport) -> Result<(), Error> {
+        self.fits = false;
+        Ok(())
+    }
+
+    fn visit_record(&mut self, record: Data, columns: Option<&[&str]>) -> Result<(), Error> {
+        let columns = columns.map(|columns| columns.iter().map(|c| c.to_string()).collect());
+        self.records.push((record, columns));
+        Ok(())
+    }
+}
+
+impl Attempt {
+    /// Passes on the results of the attempt once the repetition is taken.
+    fn replay(self, visitor: &mut Visitor) -> Result<(), Error> {
+        for row in self.rows {
+            visitor.visit_row(row)?;
+        }
+        for (record, columns) in self.records {
+            let columns = columns.as_ref().map(|columns| {
+                columns.iter().map(String::as_str).collect::<Vec<_>>()
+            });
+            visitor.visit_record(record, columns.as_ref().map(Vec::as_slice))?;
+        }
+        Ok(())
+    }
+}
+
+/// What the lines are converted into.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Build {
+    /// Nothing: the lines are only validated
+    Nothing,
+    /// Records that are passed to the visitor one row at a time
+    Records,
+    /// Data shaped like the whole layout
+    Tree,
+}
+
+/// The surroundings of a layout that is matched against the lines.
+#[derive(Clone, Copy)]
+struct Context<'c> {
+    /// The number of lines left to the following layouts
+    reserve: usize,
+    /// Whether the following layouts take exactly `reserve` lines
+    fixed: bool,
+    /// The cells of the header row that the layout follows
+    columns: Option<&'c [&'c str]>,
+    /// Whether the layout is a header row, whose lines aren't records
+    header: bool,
+}
+
+/// The records of the data being validated, read on demand. Only the records
+/// that may still be needed are kept: the ones reserved for the following
+/// layouts, and the ones of a repetition that is being attempted.
+struct Lines<R> {
+    reader: RecordReader<R>,
+    records: VecDeque<Record>,
+    /// The position of the first record of `records`
+    offset: usize,
+    /// The number of nested repetitions being attempted
+    attempts: usize,
+    build: Build,
+}
+
+impl<R: BufRead> Lines<R> {
+    fn new(reader: RecordReader<R>, build: Build) -> Self {
+        Lines {
+            reader,
+            records: VecDeque::new(),
+            offset: 0,
+            attempts: 0,
+            build,
+        }
+    }
+
+    /// Whether there is a record at `pos`.
+    fn has(&mut self, pos: usize) -> Result<bool, Error> {
+        while self.offset + self.records.len() <= pos {
+            match self.reader.read_record()? {
+                Some(record) => self.records.push_back(record),
+                None => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+
+    fn get(&self, pos: usize) -> &Record {
+        &self.records[pos - self.offset]
+    }
+
+    /// The number of the line where the record at `pos` starts, or of the line
+    /// after the last record.
+    fn line_number(&self, pos: usize) -> usize {
+        match self.records.get(pos - self.offset) {
+            Some(record) => record.line,
+            None => self.reader.line() + 1,
+        }
+    }
+
+    /// Passes on the problems of the records read so far, and drops the
+    /// records before `pos` unless a repetition may still backtrack to them.
+    fn release(&mut self, pos: usize, visitor: &mut Visitor) -> Result<(), Error> {
+        if self.attempts > 0 {
+            return Ok(());
+        }
+        for problem in self.reader.take_problems() {
+            visitor.visit_line(problem)?;
+        }
+        while self.offset < pos && !self.records.is_empty() {
+            self.records.pop_front();
+            self.offset += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Matches a record against the cells of a row, and converts it into data if
+/// `build` is set.
+fn check_row(
+    cells: &[Value],
+    names: &[Option<&str>],
+    record: &Record,
+    build: bool,
+    visitor: &mut Visitor,
+) -> Result<Option<Data>, Error> {
+    let line = &record.cells;
+    if cells.len() != line.len() {
+        visitor.visit_line(LineReport {
+            line: record.line,
+            mismatch: Mismatch::Width {
+                expected: cells.len(),
+                found: line.len(),
+            },
+        })?;
+    }
+
+    let reports = cells
+        .iter()
+        .zip(line)
+        .enumerate()
+        .map(|(col_idx, (cell, content))| {
+            let matches = cell.matches(content);
+            let (closest, reason) = if matches {
+                (None, None)
+            } else {
+                (cell.closest_alternative(content), cell.explain_mismatch(content))
+            };
+            CellReport {
+                line: record.line,
+                column: col_idx + 1,
+                content: content.to_string(),
+                expected: cell.to_string(),
+                matches,
+                closest: closest.map(str::to_owned),
+                reason,
+            }
+        })
+        .collect();
+    visitor.visit_row(reports)?;
+
+    if !build {
+        return Ok(None);
+    }
+    let fields = cells
+        .iter()
+        .zip(names)
+        .zip(line)
+        .map(|((cell, name), content)| {
+            let data = cell.convert(content).unwrap_or_else(|| Data::Str(content.clone()));
+            (name.map(str::to_owned), data)
+        })
+        .collect();
+    Ok(Some(Data::Record(fields)))
+}
+
+/// Matches the lines from `pos` against a layout, leaving the last
+/// `ctx.reserve` lines to the following layouts, and returns the position
+/// after the last consumed line, along with the data of the lines if
+/// `lines.build` is `Build::Tree`. Repeated bodies consume greedily all the
+/// lines that aren't reserved. Repetitions beyond the minimum of `many`,
+/// `some`, `optional` and `repeat` are taken if they match, or if they have
+/// the shape of the layout and the following layouts couldn't take the lines
+/// instead.
+fn check_layout<R: BufRead>(
+    layout: &Value,
+    lines: &mut Lines<R>,
+    pos: usize,
+    ctx: Context,
+    visitor: &mut Visitor,
+) -> Result<(usize, Option<Data>), Error> {
+    let tree = lines.build == Build::Tree;
+    let list = |items: Vec<Data>| if tree { Some(Data::List(items)) } else { None };
+
+    match layout {
+        Value::Row(cells, names) => {
+            if lines.has(pos + ctx.reserve)? {
+                let build = lines.build != Build::Nothing;
+                let mut data = check_row(cells, names, lines.get(pos), build, visitor)?;
+                if lines.build == Build::Records {
+                    match data.take() {
+                        Some(record) if !ctx.header => visitor.visit_record(record, ctx.columns)?,
+                        _ => (),
+                    }
+                }
+                lines.release(pos + 1, visitor)?;
+                Ok((pos + 1, data))
+            } else {
+                visitor.visit_line(LineReport {
+                    line: lines.line_number(pos),
+                    mismatch: Mismatch::MissingLine,
+                })?;
+                Ok((pos, list(Vec::new())))
+            }
+        }
+        Value::Col(children, _) if is_repeated(children) => {
+            let mut pos = pos;
+            let mut items = Vec::new();
+            while lines.has(pos + ctx.reserve)? {
+                let (next, data) = check_layout(&children[0], lines, pos, ctx, visitor)?;
+                items.extend(data);
+                pos = next;
+            }
+            Ok((pos, list(items)))
+        }
+        Value::Col(children, names) => {
+            let mut pos = pos;
+            let mut fields = Vec::new();
+            // The cells of a header row name the cells of the layout after it
+            let mut columns = None;
+            for (idx, (child, name)) in children.iter().zip(names).enumerate() {
+                let following = &children[idx + 1..];
+                let header = header(child, *name);
+                let child_columns = mem::replace(&mut columns, header.clone());
+                let child_ctx = Context {
+                    reserve: ctx.reserve + following.iter().map(min_lines).sum::<usize>(),
+                    fixed: ctx.fixed && following.iter().all(is_fixed),
+                    columns: child_columns.as_ref().map(Vec::as_slice).or(ctx.columns),
+                    header: header.is_some(),
+                };
+                let (next, data) = check_layout(child, lines, pos, child_ctx, visitor)?;
+                fields.extend(data.map(|data| (name.map(str::to_owned), data)));
+                pos = next;
+            }
+            Ok((pos, if tree { Some(Data::Record(fields)) } else { None }))
+        }
+        Value::Repeat(repetition, layout) => {
+            let mut pos = pos;
+            let mut count = 0;
+            let mut items = Vec::new();
+            // The repetitions are followed by more of themselves, unless fixed
+            let inner = Context {
+                fixed: ctx.fixed && repetition.max == Some(repetition.min),
+                ..ctx
+            };
+            while count < repetition.min {
+                let (next, data) = check_layout(layout, lines, pos, inner, visitor)?;
+                items.extend(data);
+                count += 1;
+                // A repetition that consumes nothing would repeat forever
+                if next == pos {
+                    return Ok((pos, list(items)));
+                }
+                pos = next;
+            }
+            // The optional repetitions stop at the first one that doesn't
+            // match, leaving the lines to the following layouts. If those
+            // can't take more lines, a repetition of the right shape is taken
+            // anyway so that its cells are reported as mismatches.
+            while repetition.allows(count) && lines.has(pos + ctx.reserve)? {
+                let mut attempt = Attempt::new();
+                lines.attempts += 1;
+                let result = check_layout(layout, lines, pos, inner, &mut attempt);
+                lines.attempts -= 1;
+                let (next, data) = result?;
+                if next == pos || !attempt.fits || !(attempt.matches || ctx.fixed) {
+                    break;
+                }
+                attempt.replay(visitor)?;
+                lines.release(next, visitor)?;
+                items.extend(data);
+                count += 1;
+                pos = next;
+            }
+            Ok((pos, list(items)))
+        }
+        _ => unreachable!("Only layouts are matched against lines."),
+    }
+}
+
+/// Matches the whole data against a layout, skipping the header line of the
+/// dialect, and returns the data of the lines if `lines.build` is `Build::Tree`.
+fn check_data<R: BufRead>(
+    dialect: &Dialect,
+    layout: &Value,
+    lines: &mut Lines<R>,
+    visitor: &mut Visitor,
+) -> Result<Option<Data>, Error> {
+    if !layout.is_layout() {
+        return Err(WrongTypeOfArguments(Snippet::default()).into());
+    }
+
+    let start = if dialect.header && lines.has(0)? { 1 } else { 0 };
+    lines.release(start, visitor)?;
+
+    let ctx = Context {
+        reserve: 0,
+        fixed: true,
+        columns: None,
+        header: false,
+    };
+    let (mut pos, data) = check_layout(layout, lines, start, ctx, visitor)?;
+
+    while lines.has(pos)? {
+        visitor.visit_line(LineReport {
+            line: lines.line_number(pos),
+            mismatch: Mismatch::UnexpectedLine,
+        })?;
+        pos += 1;
+        lines.release(pos, visitor)?;
+    }
+    lines.release(pos, visitor)?;
+    Ok(data)
+}
+
+/// Validates data read from a stream against a layout, passing the results to
+/// the visitor as they are found. Only the lines that the layout may still
+/// need are kept in memory.
+pub fn validate_reader<R: BufRead>(
+    dialect: &Dialect,
+    layout: &Value,
+    reader: R,
+    visitor: &mut Visitor,
+) -> Result<(), Error> {
+    let mut lines = Lines::new(RecordReader::new(dialect.clone(), reader), Build::Nothing);
+    check_data(dialect, layout, &mut lines, visitor)?;
+    Ok(())
+}
+
+/// Counts the mismatches of a validation, passing the records on to a visitor
+/// until the first mismatch.
+struct Mismatches<'v> {
+    count: usize,
+    records: Option<&'v mut Visitor>,
+}
+
+impl<'v> Mismatches<'v> {
+    fn new(records: Option<&'v mut Visitor>) -> Self {
+        Mismatches { count: 0, records }
+    }
+
+    fn result(&self) -> Result<(), Error> {
+        if self.count > 0 {
+            return Err(InvalidDataError(self.count).into());
+        }
+        Ok(())
+    }
+}
+
+impl<'v> Visitor for Mismatches<'v> {
+    fn visit_row(&mut self, cells: Vec<CellReport>) -> Result<(), Error> {
+        self.count += cells.iter().filter(|c| !c.matches).count();
+        Ok(())
+    }
+
+    fn visit_line(&mut self, _line: LineReport) -> Result<(), Error> {
+        self.count += 1;
+        Ok(())
+    }
+
+    fn visit_record(&mut self, record: Data, columns: Option<&[&str]>) -> Result<(), Error> {
+        match self.records {
+            Some(ref mut visitor) if self.count == 0 => visitor.visit_record(record, columns),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Parses data read from a stream into typed data shaped like the layout. The
+/// data has to match the layout; use `validate_reader` to find out why it
+/// doesn't.
+pub fn parse_reader<R: BufRead>(dialect: &Dialect, layout: &Value, reader: R) -> Result<Data, Error> {
+    let mut lines = Lines::new(RecordReader::new(dialect.clone(), reader), Build::Tree);
+    let mut mismatches = Mismatches::new(None);
+    let data = check_data(dialect, layout, &mut lines, &mut mismatches)?;
+
+    mismatches.result()?;
+    Ok(data.expect("Invariant: the data is built when lines.build is Build::Tree."))
+}
+
+/// Parses data read from a stream one record at a time, passing the data of
+/// each line matched against a `row` to `visitor.visit_record` without
+/// keeping the whole data in memory. Header rows aren't passed on; their cells
+/// are the columns of the records that follow them. The records are passed on
+/// until the first mismatch, and the parse fails at the end if the data
+/// doesn't match the layout.
+pub fn parse_records<R: BufRead>(
+    dialect: &Dialect,
+    layout: &Value,
+    reader: R,
+    visitor: &mut Visitor,
+) -> Result<(), Error> {
+    let mut lines = Lines::new(RecordReader::new(dialect.clone(), reader), Build::Records);
+    let mut mismatches = Mismatches::new(Some(visitor));
+    check_data(dialect, layout, &mut lines, &mut mismatches)?;
+
+    mismatches.result()
+}
+
+/// Validates data against a layout, reading it in a dialect.
+pub fn validate_str(dialect: &Dialect, layout: &Value, data: &str) -> Result<Report, Error> {
+    let mut report = Report::default();
+    validate_reader(dialect, layout, data.as_bytes(), &mut report)?;
+    report.lines.sort_by_key(|line| line.line);
+    Ok(report)
+}
+
+/// The runtime value of the main layout of the user library, and the dialect
+/// of its data files.
+pub fn file_layout<'a>(schema: &Schema<'a>) -> Result<(Dialect, Value<'a>), Error> {
+    let layout = schema
+        .value(main_layout(&schema.root)?)
+        .ok_or_else(|| WrongTypeOfArguments(Snippet::default()))?;
+    Ok(match layout.clone() {
+        Value::File(dialect, layout) => (dialect, *layout),
+        layout => (Dialect::default(), layout),
+    })
+}
+
+/// Validates a data file against the main layout of the user library. The
+/// whole report is kept in memory; use `validate_reader` to validate large
+/// files.
+pub fn validate_file(schema: &Schema, data_path: &Path) -> Result<Report, Error> {
+    let (dialect, layout) = file_layout(schema)?;
+    let data = BufReader::new(File::open(data_path)?);
+
+    let mut report = Report::default();
+    validate_reader(&dialect, &layout, data, &mut report)?;
+    report.lines.sort_by_key(|line| line.line);
+    Ok(report)
+}
+
+/// Parses a data file into typed data shaped like the main layout of the user
+/// library.
+pub fn parse_file(schema: &Schema, data_path: &Path) -> Result<Data, Error> {
+    let (dialect, layout) = file_layout(schema)?;
+    let data = BufReader::new(File::open(data_path)?);
+
+    parse_reader(&dialect, &layout, data)
+}
+
+/// Resolves and typechecks a test library named `test` without the standard
+/// library. The library declares the intrinsics it uses itself.
+#[cfg(test)]
+pub fn test_schema(source: &'static [u8]) -> Schema<'static> {
+    use std::collections::HashMap;
+
+    use nameres::AbsPath2;
+    use parse_lib;
+    use {KEYWORD_INTRINSIC, KEYWORD_ROOT};
+
+    let mut root = Item::named(KEYWORD_ROOT);
+    let mut intrinsic = Item::named(KEYWORD_INTRINSIC);
+    intrinsic.path = AbsPath2::new(vec![0]);
+    root.add_child(intrinsic);
+
+    parse_lib("test", source, &mut root, None).unwrap();
+    Schema::check(root, &HashMap::new()).unwrap()
+}
+
+#[test]
+fn test_validate_header_and_body() {
+    let source = &br#"
+intrinsic("row") as row
+intrinsic("col") as col
+intrinsic("regexp") as regexp
+intrinsic("or") as or
+intrinsic("export") as export
+
+or("mon" "tue") as day
+
+col(
+    row("name" "day")
+    col(row(regexp("[a-z]+") day))
+) as test
+
+export(test)
+"#[..];
+
+    let (_, layout) = file_layout(&test_schema(source)).unwrap();
+
+    let report = validate_str(&Dialect::default(), &layout, "name\tday\nalice\tmon\nbob\twed\n").unwrap();
+    assert_eq!(report.cells.len(), 6);
+    assert!(report.lines.is_empty());
+    assert_eq!(
+        report.mismatched_cells().collect::<Vec<_>>(),
+        vec![&CellReport {
+            line: 3,
+            column: 2,
+            content: "wed".to_owned(),
+            expected: r#"or("mon" "tue")"#.to_owned(),
+            matches: false,
+            closest: Some("mon".to_owned()),
+            reason: None,
+        }]
+    );
+
+    let report = validate_str(&Dialect::default(), &layout, "name\tday\nbob\ttues\n").unwrap();
+    assert_eq!(
+        report.mismatched_cells().next().unwrap().closest,
+        Some("tue".to_owned())
+    );
+
+    let report = validate_str(&Dialect::default(), &layout, "name\tday\nalice\tmon\textra\n").unwrap();
+    assert_eq!(
+        report.lines,
+        vec![LineReport {
+            line: 2,
+            mismatch: Mismatch::Width {
+                expected: 2,
+                found: 3
+            },
+        }]
+    );
+
+    assert!(validate_str(&Dialect::default(), &layout, "name\tday\n").unwrap().is_ok());
+
+    let report = validate_str(&Dialect::default(), &layout, "").unwrap();
+    assert_eq!(report.lines[0].mismatch, Mismatch::MissingLine);
+}
+
+#[test]
+fn test_validate_fixed_rows() {
+    let source = &br#"
+intrinsic("row") as row
+intrinsic("col") as col
+intrinsic("export") as export
+
+col(
+    row("a" "b")
+    row("c" "d")
+) as test
+
+export(test)
+"#[..];
+
+    let (_, layout) = file_layout(&test_schema(source)).unwrap();
+
+    assert!(validate_str(&Dialect::default(), &layout, "a\tb\nc\td").unwrap().is_ok());
+
+    let report = validate_str(&Dialect::default(), &layout, "a\tb\nc\td\ne\tf\n").unwrap();
+    assert_eq!(
+        report.lines,
+        vec![LineReport {
+            line: 3,
+            mismatch: Mismatch::UnexpectedLine,
+        }]
+    );
+}
+
+#[test]
+fn test_validate_repetitions() {
+    let source = &br#"
+intrinsic("row") as row
+intrinsic("col") as col
+intrinsic("regexp") as regexp
+intrinsic("many") as many
+intrinsic("optional") as optional
+intrinsic("repeat") as repeat
+intrinsic("export") as export
+
+col(
+    optional(row("name" "count"))
+    many(row(regexp("[a-z]+") regexp("[0-9]+")))
+    repeat("2" row("--" "--"))
+) as test
+
+export(test)
+"#[..];
+
+    let (_, layout) = file_layout(&test_schema(source)).unwrap();
+    assert_eq!(
+        layout.to_string(),
+        r#"col(optional(row("name" "count")) many(row(regexp("[a-z]+") regexp("[0-9]+"))) repeat("2" row("--" "--")))"#
+    );
+
+    assert!(validate_str(&Dialect::default(), &layout, "name\tcount\na\t1\n--\t--\n--\t--\n").unwrap().is_ok());
+    assert!(validate_str(&Dialect::default(), &layout, "a\t1\nb\t2\n--\t--\n--\t--\n").unwrap().is_ok());
+    assert!(validate_str(&Dialect::default(), &layout, "--\t--\n--\t--\n").unwrap().is_ok());
+
+    // The fixed footer takes the lines it needs first
+    let report = validate_str(&Dialect::default(), &layout, "a\t1\n--\t--\n").unwrap();
+    assert!(report.lines.is_empty());
+    assert!(report.mismatched_cells().all(|c| c.line == 1));
+
+    // The lines that only the repetition can take are matched against it
+    let report = validate_str(&Dialect::default(), &layout, "a\t1\n--\t--\n--\t--\n--\t--\n").unwrap();
+    assert!(report.lines.is_empty());
+    assert!(report.mismatched_cells().all(|c| c.line == 2));
+
+    let report = validate_str(&Dialect::default(), &layout, "a\t1\nB\t2\nc\t3\n--\t--\n--\t--\n").unwrap();
+    assert!(report.lines.is_empty());
+    let mismatched = report.mismatched_cells().map(|c| (c.line, c.column)).collect::<Vec<_>>();
+    assert_eq!(mismatched, [(2, 1)]);
+
+    // A line of another width ends the repetition
+    let report = validate_str(&Dialect::default(), &layout, "a\t1\nb\t2\tx\n--\t--\n--\t--\n").unwrap();
+    assert_eq!(
+        report.lines,
+        vec![
+            LineReport {
+                line: 2,
+                mismatch: Mismatch::Width { expected: 2, found: 3 },
+            },
+            LineReport {
+                line: 4,
+                mismatch: Mismatch::UnexpectedLine,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_validate_int_count() {
+    let source = &br#"
+intrinsic("row") as row
+intrinsic("repeat") as repeat
+intrinsic("export") as export
+
+repeat(2 row("a" "b")) as test
+
+export(test)
+"#[..];
+
+    let (_, layout) = file_layout(&test_schema(source)).unwrap();
+    assert_eq!(layout.to_string(), r#"repeat("2" row("a" "b"))"#);
+    assert!(validate_str(&Dialect::default(), &layout, "a\tb\na\tb\n").unwrap().is_ok());
+    assert!(!validate_str(&Dialect::default(), &layout, "a\tb\n").unwrap().is_ok());
+}
+
+#[test]
+fn test_validate_reader() {
+    let source = &br#"
+intrinsic("row") as row
+intrinsic("col") as col
+intrinsic("regexp") as regexp
+intrinsic("export") as export
+
+col(
+    row("id")
+    col(row(regexp("[0-9]+")))
+    row("end")
+) as test
+
+export(test)
+"#[..];
+
+    let (_, layout) = file_layout(&test_schema(source)).unwrap();
+
+    let mut data = String::from("id\n");
+    for idx in 0..10000 {
+        if idx % 100 == 0 {
+            data.push_str("x\n");
+        } else {
+            data.push_str(&format!("{}\n", idx));
+        }
+    }
+    data.push_str("end\n");
+
+    // Only the lookahead reserved for the footer is kept
+    let mut lines = Lines::new(RecordReader::new(Dialect::default(), data.as_bytes()), Build::Nothing);
+    let mut report = Report::default();
+    let ctx = Context {
+        reserve: 0,
+        fixed: true,
+        columns: None,
+        header: false,
+    };
+    let (pos, _) = check_layout(&layout, &mut lines, 0, ctx, &mut report).unwrap();
+    assert_eq!(pos, 10002);
+    assert!(lines.records.is_empty());
+    assert_eq!(report.cells.len(), 10002);
+    assert_eq!(report.mismatched_cells().count(), 100);
+
+    let mut report = Report::default();
+    let err = validate_reader(
+        &Dialect::default(),
+        &layout,
+        data.as_bytes(),
+        &mut ErrorLimit::new(&mut report, 10),
+    ).unwrap_err();
+    assert!(err.downcast_ref::<TooManyMismatchesError>().is_some());
+    assert_eq!(report.mismatched_cells().count(), 10);
+    assert_eq!(report.cells.last().unwrap().line, 902);
+
+    let mut report = Report::default();
+    validate_reader(
+        &Dialect::default(),
+        &layout,
+        data.as_bytes(),
+        &mut ErrorLimit::new(&mut report, 0),
+    ).unwrap();
+    assert_eq!(report.mismatched_cells().count(), 100);
+}
+
+#[test]
+fn test_parse_records() {
+    let source = &br#"
+intrinsic("row") as row
+intrinsic("col") as col
+intrinsic("regexp") as regexp
+intrinsic("many") as many
+intrinsic("export") as export
+
+col(
+    row("name" "count")
+    many(row(regexp("[a-z]+") regexp("[0-9]+")))
+    row("total" regexp("[0-9]+"))
+) as test
+
+export(test)
+"#[..];
+
+    #[derive(Default)]
+    struct Records(Vec<(Data, Option<Vec<String>>)>);
+
+    impl Visitor for Records {
+        fn visit_row(&mut self, _cells: Vec<CellReport>) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn visit_line(&mut self, _line: LineReport) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn visit_record(&mut self, record: Data, columns: Option<&[&str]>) -> Result<(), Error> {
+            let columns = columns.map(|columns| columns.iter().map(|c| c.to_string()).collect());
+            self.0.push((record, columns));
+            Ok(())
+        }
+    }
+
+    let (_, layout) = file_layout(&test_schema(source)).unwrap();
+    let columns = Some(vec!["name".to_owned(), "count".to_owned()]);
+
+    // The header names only the rows of the layout right after it
+    let mut records = Records::default();
+    let data = "name\tcount\nalice\t5\nbob\t3\ntotal\t8\n";
+    parse_records(&Dialect::default(), &layout, data.as_bytes(), &mut records).unwrap();
+    assert_eq!(records.0.len(), 3);
+    assert_eq!(records.0[0].1, columns);
+    assert_eq!(records.0[1].1, columns);
+    assert_eq!(
+        records.0[2],
+        (
+            Data::Record(vec![
+                (None, Data::Tag("total".to_owned())),
+                (None, Data::Str("8".to_owned())),
+            ]),
+            None
+        )
+    );
+
+    // The records stop at the first mismatch
+    let mut records = Records::default();
+    let data = "name\tcount\nalice\t5\nbob\tx\ncarol\t1\ntotal\t8\n";
+    let err = parse_records(&Dialect::default(), &layout, data.as_bytes(), &mut records).unwrap_err();
+    assert_eq!(err.downcast_ref::<InvalidDataError>().map(|e| e.0), Some(1));
+    assert_eq!(records.0.len(), 1);
+}
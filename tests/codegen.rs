@@ -0,0 +1,74 @@
+extern crate kadouchi;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// The code generated from `weekdays.ku`, with the paths relative to the
+/// fixtures. `test_generated_is_current` keeps it up to date.
+#[allow(dead_code)]
+mod weekdays {
+    include!("fixtures/weekdays.rs");
+}
+
+/// The code generated from `nested.ku`, a `col` of a repeated `col` whose row
+/// is named with `as`.
+#[allow(dead_code)]
+mod nested {
+    include!("fixtures/nested.rs");
+}
+
+#[test]
+fn test_generate_weekdays() {
+    let mut bytestore = Vec::new();
+    let schema = Path::new("tests/fixtures/weekdays.ku");
+
+    let root = kadouchi::parse_with_stdlib(schema, &mut bytestore).unwrap();
+    let code = kadouchi::generate_rust(&root).unwrap();
+
+    assert!(code.contains("pub enum Day {\n    Mon,\n    Tue,\n    Wed,"));
+    assert!(code.contains("    pub date: ::kadouchi::Date,\n    pub count: u64,\n    pub score: f64,\n"));
+    assert!(code.contains("pub struct Weekdays {\n    pub data: Vec<DataRow>,\n}"));
+    assert!(code.contains("pub fn parse<R: ::std::io::BufRead>(reader: R) -> Result<Weekdays, "));
+    assert!(code.contains("pub const STD: Option<&str> = None;"));
+}
+
+/// The code generated from a fixture schema, with the paths relative to the
+/// fixtures.
+fn generate_fixture(name: &str) -> String {
+    let mut bytestore = Vec::new();
+    let schema = Path::new("tests/fixtures").join(format!("{}.ku", name));
+
+    let root = kadouchi::parse_with_stdlib(&schema, &mut bytestore).unwrap();
+    let code = kadouchi::generate_rust(&root).unwrap();
+
+    let fixtures = Path::new("tests/fixtures").canonicalize().unwrap();
+    code.replace(&format!("{}/", fixtures.display()), "")
+}
+
+#[test]
+fn test_generated_is_current() {
+    assert_eq!(generate_fixture("weekdays"), include_str!("fixtures/weekdays.rs"));
+    assert_eq!(generate_fixture("nested"), include_str!("fixtures/nested.rs"));
+}
+
+#[test]
+fn test_generated_parse() {
+    let reader = BufReader::new(File::open("tests/fixtures/names.tsv").unwrap());
+    let data = weekdays::parse(reader).unwrap();
+
+    assert_eq!(data.data.len(), 6);
+    assert_eq!(data.data[0].name, "alice");
+    assert_eq!(data.data[0].day, weekdays::Day::Mon);
+    assert_eq!(data.data[0].count, 5);
+}
+
+#[test]
+fn test_generated_nested_parse() {
+    let reader = BufReader::new(File::open("tests/fixtures/nested.tsv").unwrap());
+    let data = nested::parse(reader).unwrap();
+
+    assert_eq!(data.nested_1.len(), 2);
+    assert_eq!(data.nested_1[1].field_1, "bob");
+    assert_eq!(data.nested_1[1].field_2, 7);
+}
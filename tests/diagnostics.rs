@@ -0,0 +1,56 @@
+extern crate kadouchi;
+
+use std::path::Path;
+
+#[test]
+fn test_unknown_name_snippet() {
+    let mut bytestore = Vec::new();
+
+    let err = kadouchi::parse_with_stdlib(Path::new("tests/fixtures/unknown_name.ku"), &mut bytestore)
+        .unwrap_err();
+
+    assert_eq!(
+        err.to_string(),
+        "The name nonexistent was not found in lexical scope
+ --> tests/fixtures/unknown_name.ku:3:10
+  |
+3 | row(date\tnonexistent)
+  |         \t^^^^^^^^^^^"
+    );
+}
+
+#[test]
+fn test_reference_cycle_snippet() {
+    let mut bytestore = Vec::new();
+
+    let err = kadouchi::parse_with_stdlib(Path::new("tests/fixtures/cycle.ku"), &mut bytestore)
+        .unwrap_err();
+
+    assert_eq!(kadouchi::exit_code(&err), 4);
+    assert_eq!(
+        err.to_string(),
+        "The items refer to each other in a cycle: line -> cell -> line.
+ --> tests/fixtures/cycle.ku:3:8
+  |
+3 | or(\"x\" line) as cell
+  |        ^^^^"
+    );
+}
+
+#[test]
+fn test_invalid_pattern_snippet() {
+    let mut bytestore = Vec::new();
+
+    let err = kadouchi::parse_with_stdlib(Path::new("tests/fixtures/invalid_pattern.ku"), &mut bytestore)
+        .unwrap_err();
+
+    assert_eq!(kadouchi::exit_code(&err), 5);
+    let message = err.to_string();
+    assert!(message.starts_with("Invalid regular expression \"[0-9\": regex parse error:\n    [0-9\n"));
+    assert!(message.ends_with(
+        " --> tests/fixtures/invalid_pattern.ku:3:1
+  |
+3 | regexp(\"[0-9\") as broken
+  | ^^^^^^^^^^^^^^^^^^^^^^^^"
+    ));
+}
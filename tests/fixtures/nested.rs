@@ -0,0 +1,47 @@
+// Generated by kadouchi from nested.ku. Do not edit.
+
+/// The `std` library of the schema, or `None` for the bundled one.
+pub const STD: Option<&str> = None;
+/// The `prelude` library of the schema, or `None` for the bundled one.
+pub const PRELUDE: Option<&str> = None;
+/// The schema and the libraries it imports, in dependency order.
+pub const LIBRARIES: &[(&str, &str)] = &[
+    ("nested", include_str!("nested.ku")),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Nested1Row {
+    pub field_1: String,
+    pub field_2: u64,
+}
+
+impl Nested1Row {
+    pub fn from_data(data: ::kadouchi::Data) -> Result<Self, ::kadouchi::Error> {
+        let mut fields = data.into_fields()?;
+        let field_1 = fields.take()?.into_string()?;
+        let field_2 = fields.take()?.into_uint()?;
+        Ok(Nested1Row { field_1, field_2 })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Nested {
+    pub nested_1: Vec<Nested1Row>,
+}
+
+impl Nested {
+    pub fn from_data(data: ::kadouchi::Data) -> Result<Self, ::kadouchi::Error> {
+        let mut fields = data.into_fields()?;
+        let nested_1 = fields.take()?.into_vec(Nested1Row::from_data)?;
+        Ok(Nested { nested_1 })
+    }
+}
+
+/// Parses a data file into `Nested`, checking it against the schema.
+pub fn parse<R: ::std::io::BufRead>(reader: R) -> Result<Nested, ::kadouchi::Error> {
+    let search_path = ::kadouchi::SearchPath::from_env();
+    let schema = ::kadouchi::parse_sources(STD, PRELUDE, LIBRARIES, &search_path)?;
+    let (dialect, layout) = ::kadouchi::file_layout(&schema)?;
+    let data = ::kadouchi::parse_reader(&dialect, &layout, reader)?;
+    Nested::from_data(data)
+}
@@ -0,0 +1,95 @@
+// Generated by kadouchi from weekdays.ku. Do not edit.
+
+/// The `std` library of the schema, or `None` for the bundled one.
+pub const STD: Option<&str> = None;
+/// The `prelude` library of the schema, or `None` for the bundled one.
+pub const PRELUDE: Option<&str> = None;
+/// The schema and the libraries it imports, in dependency order.
+pub const LIBRARIES: &[(&str, &str)] = &[
+    ("weekdays", include_str!("weekdays.ku")),
+];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Day {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+impl Day {
+    pub fn from_data(data: ::kadouchi::Data) -> Result<Self, ::kadouchi::Error> {
+        match data.into_tag()?.as_str() {
+            "mon" => Ok(Day::Mon),
+            "tue" => Ok(Day::Tue),
+            "wed" => Ok(Day::Wed),
+            "thu" => Ok(Day::Thu),
+            "fri" => Ok(Day::Fri),
+            "sat" => Ok(Day::Sat),
+            "sun" => Ok(Day::Sun),
+            tag => Err(::kadouchi::DataShapeError(
+                "a tag of Day".to_owned(),
+                format!("{:?}", tag),
+            ).into()),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Day::Mon => "mon",
+            Day::Tue => "tue",
+            Day::Wed => "wed",
+            Day::Thu => "thu",
+            Day::Fri => "fri",
+            Day::Sat => "sat",
+            Day::Sun => "sun",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataRow {
+    pub name: String,
+    pub day: Day,
+    pub date: ::kadouchi::Date,
+    pub count: u64,
+    pub score: f64,
+}
+
+impl DataRow {
+    pub fn from_data(data: ::kadouchi::Data) -> Result<Self, ::kadouchi::Error> {
+        let mut fields = data.into_fields()?;
+        let name = fields.take()?.into_string()?;
+        let day = Day::from_data(fields.take()?)?;
+        let date = fields.take()?.into_date()?;
+        let count = fields.take()?.into_uint()?;
+        let score = fields.take()?.into_float()?;
+        Ok(DataRow { name, day, date, count, score })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Weekdays {
+    pub data: Vec<DataRow>,
+}
+
+impl Weekdays {
+    pub fn from_data(data: ::kadouchi::Data) -> Result<Self, ::kadouchi::Error> {
+        let mut fields = data.into_fields()?;
+        fields.take()?;
+        let data = fields.take()?.into_vec(DataRow::from_data)?;
+        Ok(Weekdays { data })
+    }
+}
+
+/// Parses a data file into `Weekdays`, checking it against the schema.
+pub fn parse<R: ::std::io::BufRead>(reader: R) -> Result<Weekdays, ::kadouchi::Error> {
+    let search_path = ::kadouchi::SearchPath::from_env();
+    let schema = ::kadouchi::parse_sources(STD, PRELUDE, LIBRARIES, &search_path)?;
+    let (dialect, layout) = ::kadouchi::file_layout(&schema)?;
+    let data = ::kadouchi::parse_reader(&dialect, &layout, reader)?;
+    Weekdays::from_data(data)
+}
@@ -0,0 +1,332 @@
+extern crate kadouchi;
+
+use std::path::Path;
+
+#[test]
+fn test_import() {
+    let mut bytestore = Vec::new();
+
+    let root = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/census.ku"),
+        &mut bytestore,
+    ).unwrap();
+
+    // The imported libraries are built before the libraries that import them
+    let libs = root
+        .root
+        .ns
+        .items
+        .iter()
+        .filter_map(|lib| lib.local_name)
+        .collect::<Vec<_>>();
+    assert_eq!(libs, ["intrinsic", "std", "prelude", "units", "people", "census"]);
+
+    let report =
+        kadouchi::validate_file(&root, Path::new("tests/fixtures/imports/census.tsv")).unwrap();
+    let mismatched = report
+        .mismatched_cells()
+        .map(|c| (c.line, c.column))
+        .collect::<Vec<_>>();
+    assert_eq!(mismatched, [(3, 2), (3, 3)]);
+}
+
+#[test]
+fn test_import_private() {
+    let mut bytestore = Vec::new();
+
+    let err = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/private.ku"),
+        &mut bytestore,
+    ).unwrap_err();
+
+    assert_eq!(kadouchi::exit_code(&err), 4);
+    assert!(err.to_string().starts_with("Accessed item is private unit_name"));
+}
+
+#[test]
+fn test_import_cycle() {
+    let mut bytestore = Vec::new();
+
+    let err = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/cycle_a.ku"),
+        &mut bytestore,
+    ).unwrap_err();
+
+    assert_eq!(kadouchi::exit_code(&err), 4);
+    assert!(err.to_string().starts_with(
+        "The libraries import each other in a cycle: cycle_a -> cycle_b -> cycle_a."
+    ));
+    assert!(err.to_string().contains("cycle_b.ku:1:8"));
+}
+
+#[test]
+fn test_import_missing() {
+    let mut bytestore = Vec::new();
+
+    let err = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/missing.ku"),
+        &mut bytestore,
+    ).unwrap_err();
+
+    assert_eq!(kadouchi::exit_code(&err), 4);
+    assert!(err.to_string().starts_with("The library nowhere was not found in the search path."));
+    assert!(err.to_string().contains("missing.ku:1:14"));
+}
+
+#[test]
+fn test_import_not_transitive() {
+    let mut bytestore = Vec::new();
+
+    let err = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/transitive.ku"),
+        &mut bytestore,
+    ).unwrap_err();
+
+    assert_eq!(kadouchi::exit_code(&err), 4);
+    assert!(err.to_string().starts_with("The name units was not found in lexical scope"));
+    assert!(err.to_string().contains("transitive.ku:4:28"));
+
+    let mut bytestore = Vec::new();
+
+    let err = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/transitive_use.ku"),
+        &mut bytestore,
+    ).unwrap_err();
+
+    assert_eq!(kadouchi::exit_code(&err), 4);
+    assert!(err.to_string().starts_with("The name units was not found in lexical scope"));
+}
+
+#[test]
+fn test_use() {
+    let mut bytestore = Vec::new();
+
+    let root = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/visits.ku"),
+        &mut bytestore,
+    ).unwrap();
+
+    let report =
+        kadouchi::validate_file(&root, Path::new("tests/fixtures/imports/visits.tsv")).unwrap();
+    let mismatched = report
+        .mismatched_cells()
+        .map(|c| (c.line, c.column))
+        .collect::<Vec<_>>();
+    assert_eq!(mismatched, [(3, 3), (3, 4), (3, 5)]);
+}
+
+#[test]
+fn test_use_through_alias() {
+    for name in &["aliased_glob", "aliased_list"] {
+        let mut bytestore = Vec::new();
+        let schema = format!("tests/fixtures/imports/{}.ku", name);
+        let data = format!("tests/fixtures/imports/{}.tsv", name);
+
+        let root = kadouchi::parse_with_stdlib(Path::new(&schema), &mut bytestore).unwrap();
+
+        let report = kadouchi::validate_file(&root, Path::new(&data)).unwrap();
+        let mismatched = report
+            .mismatched_cells()
+            .map(|c| (c.line, c.column))
+            .collect::<Vec<_>>();
+        assert_eq!(mismatched, [(2, 2)]);
+    }
+}
+
+#[test]
+fn test_use_glob_skips_private() {
+    let mut bytestore = Vec::new();
+
+    let err = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/glob_private.ku"),
+        &mut bytestore,
+    ).unwrap_err();
+
+    assert_eq!(kadouchi::exit_code(&err), 4);
+    assert!(err.to_string().starts_with("The name unit_name was not found in lexical scope"));
+}
+
+#[test]
+fn test_use_private() {
+    let mut bytestore = Vec::new();
+
+    let err = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/use_private.ku"),
+        &mut bytestore,
+    ).unwrap_err();
+
+    assert_eq!(kadouchi::exit_code(&err), 4);
+    assert!(err.to_string().starts_with("Accessed item is private unit_name"));
+    assert!(err.to_string().contains("use_private.ku:3:15"));
+}
+
+#[test]
+fn test_use_private_single() {
+    let mut bytestore = Vec::new();
+
+    let err = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/use_private_single.ku"),
+        &mut bytestore,
+    ).unwrap_err();
+
+    assert_eq!(kadouchi::exit_code(&err), 4);
+    assert!(err.to_string().starts_with("Accessed item is private unit_name"));
+    assert!(err.to_string().contains("use_private_single.ku:3:11"));
+
+    let mut bytestore = Vec::new();
+
+    // The modules on the way are checked too
+    let err = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/use_private_module.ku"),
+        &mut bytestore,
+    ).unwrap_err();
+
+    assert_eq!(kadouchi::exit_code(&err), 4);
+    assert!(err.to_string().starts_with("Accessed item is private unit"));
+}
+
+#[test]
+fn test_no_prelude() {
+    let mut bytestore = Vec::new();
+
+    let err = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/no_prelude.ku"),
+        &mut bytestore,
+    ).unwrap_err();
+
+    // int and row are imported explicitly, but str of the prelude isn't
+    assert_eq!(kadouchi::exit_code(&err), 4);
+    assert!(err.to_string().starts_with("The name str was not found in lexical scope"));
+    assert!(err.to_string().contains("no_prelude.ku:5:5"));
+}
+
+#[test]
+fn test_glob_shadowing() {
+    let mut bytestore = Vec::new();
+
+    let root = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/shadowing.ku"),
+        &mut bytestore,
+    ).unwrap();
+
+    let report =
+        kadouchi::validate_file(&root, Path::new("tests/fixtures/imports/shadowing.tsv")).unwrap();
+    let mismatched = report
+        .mismatched_cells()
+        .map(|c| (c.line, c.column))
+        .collect::<Vec<_>>();
+    assert_eq!(mismatched, [(2, 1)]);
+}
+
+#[test]
+fn test_glob_ambiguity() {
+    let mut bytestore = Vec::new();
+
+    kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/unused_ambiguity.ku"),
+        &mut bytestore,
+    ).unwrap();
+
+    let mut bytestore = Vec::new();
+
+    let err = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/ambiguity.ku"),
+        &mut bytestore,
+    ).unwrap_err();
+
+    assert_eq!(kadouchi::exit_code(&err), 4);
+    assert!(err.to_string().starts_with(
+        "The name cm is ambiguous: it's imported from both units.cm and lengths.cm."
+    ));
+    assert!(err.to_string().contains("ambiguity.ku:6:8"));
+}
+
+#[test]
+fn test_reexport() {
+    let mut bytestore = Vec::new();
+
+    let root = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/catalog.ku"),
+        &mut bytestore,
+    ).unwrap();
+
+    let report =
+        kadouchi::validate_file(&root, Path::new("tests/fixtures/imports/catalog.tsv")).unwrap();
+    let mismatched = report
+        .mismatched_cells()
+        .map(|c| (c.line, c.column))
+        .collect::<Vec<_>>();
+    assert_eq!(mismatched, [(2, 3), (2, 4)]);
+}
+
+#[test]
+fn test_library_private() {
+    let mut bytestore = Vec::new();
+
+    let err = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/private_module.ku"),
+        &mut bytestore,
+    ).unwrap_err();
+
+    // The unit of sizes is reachable in shapes but not from other libraries
+    assert_eq!(kadouchi::exit_code(&err), 4);
+    assert!(err.to_string().starts_with("Accessed item is private unit"));
+    assert!(err.to_string().contains("private_module.ku:3:18"));
+}
+
+#[test]
+fn test_export_errors() {
+    let mut bytestore = Vec::new();
+
+    let err = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/early_export.ku"),
+        &mut bytestore,
+    ).unwrap_err();
+
+    assert_eq!(kadouchi::exit_code(&err), 4);
+    assert!(err.to_string().starts_with("The item early is exported before its definition."));
+    assert!(err.to_string().contains("early_export.ku:1:8"));
+
+    let mut bytestore = Vec::new();
+
+    let err = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/twice_export.ku"),
+        &mut bytestore,
+    ).unwrap_err();
+
+    assert_eq!(kadouchi::exit_code(&err), 4);
+    assert!(err.to_string().starts_with("The item twice is exported twice."));
+    assert!(err.to_string().contains("twice_export.ku:4:8"));
+
+    // A re-exported name can't be exported again either
+    let mut bytestore = Vec::new();
+
+    let err = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/reexport_twice.ku"),
+        &mut bytestore,
+    ).unwrap_err();
+
+    assert_eq!(kadouchi::exit_code(&err), 4);
+    assert!(err.to_string().starts_with("The item kept is exported twice."));
+    assert!(err.to_string().contains("reexport_twice.ku:4:8"));
+}
+
+#[test]
+fn test_glob_reexport() {
+    let mut bytestore = Vec::new();
+
+    let root = kadouchi::parse_with_stdlib(
+        Path::new("tests/fixtures/imports/glob_reexport.ku"),
+        &mut bytestore,
+    ).unwrap();
+
+    let report = kadouchi::validate_file(
+        &root,
+        Path::new("tests/fixtures/imports/glob_reexport.tsv"),
+    ).unwrap();
+    let mismatched = report
+        .mismatched_cells()
+        .map(|c| (c.line, c.column))
+        .collect::<Vec<_>>();
+    assert_eq!(mismatched, [(2, 2)]);
+}
@@ -0,0 +1,99 @@
+extern crate kadouchi;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use kadouchi::{Data, Date, InvalidDataError};
+
+#[test]
+fn test_parse_names() {
+    let mut bytestore = Vec::new();
+
+    let root = kadouchi::parse_with_stdlib(Path::new("tests/fixtures/weekdays.ku"), &mut bytestore)
+        .unwrap();
+
+    let data = kadouchi::parse_file(&root, Path::new("tests/fixtures/names.tsv")).unwrap();
+
+    let rows = match data.field("data") {
+        Some(Data::List(rows)) => rows,
+        other => panic!("Expected a list of rows, got {:?}", other),
+    };
+    assert_eq!(rows.len(), 6);
+    assert_eq!(
+        rows[0],
+        Data::Record(vec![
+            (None, Data::Str("alice".to_owned())),
+            (None, Data::Tag("mon".to_owned())),
+            (
+                None,
+                Data::Date(Date {
+                    year: 2018,
+                    month: 6,
+                    day: 20,
+                })
+            ),
+            (None, Data::UInt(5)),
+            (None, Data::Float(5.5)),
+        ])
+    );
+}
+
+#[test]
+fn test_parse_invalid() {
+    let mut bytestore = Vec::new();
+
+    let root =
+        kadouchi::parse_with_stdlib(Path::new("tests/fixtures/simple.ku"), &mut bytestore).unwrap();
+
+    let err = kadouchi::parse_file(&root, Path::new("tests/fixtures/names.tsv")).unwrap_err();
+    assert_eq!(err.downcast_ref::<InvalidDataError>().map(|e| e.0), Some(3));
+}
+
+#[test]
+fn test_convert_ndjson() {
+    let mut bytestore = Vec::new();
+
+    let root = kadouchi::parse_with_stdlib(Path::new("tests/fixtures/weekdays.ku"), &mut bytestore)
+        .unwrap();
+
+    let data = kadouchi::parse_file(&root, Path::new("tests/fixtures/names.tsv")).unwrap();
+    let mut out = Vec::new();
+    kadouchi::write_ndjson(&data, &mut out).unwrap();
+
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(out.lines().count(), 6);
+    assert_eq!(
+        out.lines().next().unwrap(),
+        r#"{"name":"alice","day":"mon","date":"2018-06-20","count":5,"score":5.5}"#
+    );
+}
+
+#[test]
+fn test_stream_ndjson() {
+    let mut bytestore = Vec::new();
+
+    let root = kadouchi::parse_with_stdlib(Path::new("tests/fixtures/weekdays.ku"), &mut bytestore)
+        .unwrap();
+
+    let data = kadouchi::parse_file(&root, Path::new("tests/fixtures/names.tsv")).unwrap();
+    let mut expected = Vec::new();
+    kadouchi::write_ndjson(&data, &mut expected).unwrap();
+
+    let (dialect, layout) = kadouchi::file_layout(&root).unwrap();
+    let reader = BufReader::new(File::open("tests/fixtures/names.tsv").unwrap());
+    let mut records = kadouchi::NdjsonWriter::new(Vec::new());
+    kadouchi::parse_records(&dialect, &layout, reader, &mut records).unwrap();
+    assert_eq!(
+        String::from_utf8(records.into_inner()).unwrap(),
+        String::from_utf8(expected).unwrap()
+    );
+
+    let root =
+        kadouchi::parse_with_stdlib(Path::new("tests/fixtures/simple.ku"), &mut bytestore).unwrap();
+    let (dialect, layout) = kadouchi::file_layout(&root).unwrap();
+    let reader = BufReader::new(File::open("tests/fixtures/names.tsv").unwrap());
+    let mut records = kadouchi::NdjsonWriter::new(Vec::new());
+    let err = kadouchi::parse_records(&dialect, &layout, reader, &mut records).unwrap_err();
+    assert_eq!(err.downcast_ref::<InvalidDataError>().map(|e| e.0), Some(3));
+}
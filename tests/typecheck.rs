@@ -31,3 +31,44 @@ fn test_typecheck_simple_fail() {
         format!("{:?}", err.cause()).contains("Type error")
     );
 }
+
+#[test]
+fn test_typecheck_or_rejects_layouts() {
+    let mut bytestore = Vec::new();
+
+    let err = kadouchi::parse_with_stdlib(Path::new("tests/fixtures/or_layout.ku"), &mut bytestore)
+        .unwrap_err();
+
+    assert_eq!(kadouchi::exit_code(&err), 5);
+    assert!(err.to_string().starts_with("Wrong type of arguments."));
+}
+
+#[test]
+fn test_typecheck_int_count() {
+    let mut bytestore = Vec::new();
+
+    kadouchi::parse_with_stdlib(Path::new("tests/fixtures/int_count.ku"), &mut bytestore)
+        .unwrap();
+
+    let mut bytestore = Vec::new();
+    let err = kadouchi::parse_with_stdlib(Path::new("tests/fixtures/zero_count.ku"), &mut bytestore)
+        .unwrap_err();
+
+    assert_eq!(kadouchi::exit_code(&err), 5);
+}
+
+#[test]
+fn test_typecheck_literal_alias() {
+    let mut bytestore = Vec::new();
+
+    let schema = kadouchi::parse_with_stdlib(Path::new("tests/fixtures/literal_alias.ku"), &mut bytestore)
+        .unwrap();
+    let (_, layout) = kadouchi::file_layout(&schema).unwrap();
+    assert_eq!(layout.to_string(), r#"col(row("day") col(row(or("mon" "tue"))))"#);
+
+    let mut bytestore = Vec::new();
+    let err = kadouchi::parse_with_stdlib(Path::new("tests/fixtures/literal_call.ku"), &mut bytestore)
+        .unwrap_err();
+
+    assert_eq!(kadouchi::exit_code(&err), 5);
+}
@@ -0,0 +1,138 @@
+extern crate kadouchi;
+
+use std::path::Path;
+
+#[test]
+fn test_validate_names() {
+    let mut bytestore = Vec::new();
+
+    let root =
+        kadouchi::parse_with_stdlib(Path::new("tests/fixtures/simple.ku"), &mut bytestore).unwrap();
+
+    let report = kadouchi::validate_file(&root, Path::new("tests/fixtures/names.tsv")).unwrap();
+
+    assert!(report.lines.is_empty());
+    assert_eq!(report.cells.len(), 35);
+    assert!(report.cells[..5].iter().all(|c| c.matches));
+
+    // Only the days other than "mon" and "tue" don't match
+    let mismatched = report.mismatched_cells().collect::<Vec<_>>();
+    assert_eq!(mismatched.len(), 3);
+    assert!(mismatched.iter().all(|c| c.column == 2));
+}
+
+#[test]
+fn test_validate_csv() {
+    let mut bytestore = Vec::new();
+
+    let root =
+        kadouchi::parse_with_stdlib(Path::new("tests/fixtures/notes.ku"), &mut bytestore).unwrap();
+
+    let report = kadouchi::validate_file(&root, Path::new("tests/fixtures/notes.csv")).unwrap();
+
+    assert!(report.lines.is_empty());
+    assert_eq!(report.cells.len(), 12);
+
+    // The quoted cells are unquoted, even across line breaks
+    assert_eq!(report.cells[4].content, "hello, world");
+    assert_eq!(report.cells[7].content, "say \"hi\"");
+    assert_eq!(report.cells[10].content, "two\r\nlines");
+    assert_eq!(report.cells[11].line, 4);
+    assert_eq!(report.mismatched_cells().count(), 0);
+}
+
+#[test]
+fn test_validate_fixed_width() {
+    let mut bytestore = Vec::new();
+
+    let root =
+        kadouchi::parse_with_stdlib(Path::new("tests/fixtures/stock.ku"), &mut bytestore).unwrap();
+
+    let report = kadouchi::validate_file(&root, Path::new("tests/fixtures/stock.txt")).unwrap();
+
+    assert!(report.lines.is_empty());
+    assert_eq!(report.cells.len(), 9);
+
+    // The padding is trimmed, and a short last line only has the cells it reaches
+    assert_eq!(report.cells[1].content, "12");
+    assert_eq!(report.cells[2].content, "in stock");
+    assert_eq!(report.cells[8].content, "");
+    let mismatched = report.mismatched_cells().collect::<Vec<_>>();
+    assert_eq!(mismatched.len(), 1);
+    assert_eq!((mismatched[0].line, mismatched[0].column), (3, 2));
+}
+
+#[test]
+fn test_validate_forward_references() {
+    let mut bytestore = Vec::new();
+
+    let root =
+        kadouchi::parse_with_stdlib(Path::new("tests/fixtures/forward.ku"), &mut bytestore).unwrap();
+
+    let report = kadouchi::validate_file(&root, Path::new("tests/fixtures/names.tsv")).unwrap();
+
+    assert_eq!(report.mismatched_cells().count(), 0);
+}
+
+#[test]
+fn test_validate_ranges() {
+    let mut bytestore = Vec::new();
+
+    let root =
+        kadouchi::parse_with_stdlib(Path::new("tests/fixtures/ranges.ku"), &mut bytestore).unwrap();
+
+    let report = kadouchi::validate_file(&root, Path::new("tests/fixtures/names.tsv")).unwrap();
+
+    // Only the count of dave is out of range
+    let mismatched = report.mismatched_cells().collect::<Vec<_>>();
+    assert_eq!(mismatched.len(), 1);
+    assert_eq!(mismatched[0].line, 5);
+    assert_eq!(mismatched[0].expected, "uint(0 5)");
+    assert_eq!(
+        mismatched[0].reason.as_ref().unwrap(),
+        "8 is out of the range 0..5"
+    );
+}
+
+#[test]
+fn test_validate_dates() {
+    let mut bytestore = Vec::new();
+
+    let root =
+        kadouchi::parse_with_stdlib(Path::new("tests/fixtures/dates.ku"), &mut bytestore).unwrap();
+
+    let report = kadouchi::validate_file(&root, Path::new("tests/fixtures/dates.tsv")).unwrap();
+
+    assert!(report.lines.is_empty());
+    let mismatched = report
+        .mismatched_cells()
+        .map(|c| (c.line, c.column, c.reason.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        mismatched,
+        vec![
+            (3, 1, Some("month 2 of 2018 has 28 days".to_owned())),
+            (3, 3, Some("24 is not an hour".to_owned())),
+            (4, 2, Some("2019-05-01 is not in the era 平成".to_owned())),
+            // Written without the offset
+            (4, 4, None),
+        ]
+    );
+}
+
+#[test]
+fn test_validate_std_regexp() {
+    let mut bytestore = Vec::new();
+
+    let root =
+        kadouchi::parse_with_stdlib(Path::new("tests/fixtures/codes.ku"), &mut bytestore).unwrap();
+
+    let report = kadouchi::validate_file(&root, Path::new("tests/fixtures/codes.tsv")).unwrap();
+
+    // The pattern is anchored to the whole cell
+    let mismatched = report
+        .mismatched_cells()
+        .map(|c| (c.line, c.column))
+        .collect::<Vec<_>>();
+    assert_eq!(mismatched, [(2, 1), (3, 1)]);
+}